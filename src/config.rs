@@ -0,0 +1,373 @@
+// Module config
+
+use crate::lang::{
+    builtin, lookup, lookup_ellipsis, CommentSyntax, EllipsisEntry, SyntaxEntry, SyntaxTable,
+};
+use crate::parser::{split_part, Keywords, Rename};
+use crate::util::{today, BlockComment, Schedule, Variable, Variables};
+use encoding_rs::Encoding;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The options for parsing a text, e.g.
+/// ```
+/// use snips::config::ParseOptions;
+/// let options = ParseOptions::new().comment(["//"]).exercise_solution(true);
+/// ```
+/// The command line options of the same name are explained in `Setting`.
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    pub comment: Vec<String>,
+    pub block_comment: Vec<BlockComment>,
+    pub lang: Vec<SyntaxEntry>,
+    pub lang_file: Option<SyntaxTable>,
+    pub marker_prefix: String,
+    pub keyword: Vec<Rename>,
+    pub exercise_solution: bool,
+    pub reveal: Vec<String>,
+    pub reveal_schedule: Option<Schedule>,
+    pub define: Vec<String>,
+    pub var: Vec<Variable>,
+    pub var_file: Option<Variables>,
+    pub ellipsis: Vec<EllipsisEntry>,
+    pub ellipsis_indent: bool,
+    pub strict: bool,
+    pub banner: Option<String>,
+    pub header_snippets: bool,
+    pub normalize: bool,
+    pub encoding: Option<&'static Encoding>,
+}
+
+impl Default for ParseOptions {
+    /// The same defaults as on the command line, e.g. `#` as the
+    /// comment symbol.
+    fn default() -> Self {
+        ParseOptions {
+            comment: vec!["#".to_string()],
+            block_comment: Vec::new(),
+            lang: Vec::new(),
+            lang_file: None,
+            marker_prefix: String::new(),
+            keyword: Vec::new(),
+            exercise_solution: false,
+            reveal: Vec::new(),
+            reveal_schedule: None,
+            define: Vec::new(),
+            var: Vec::new(),
+            var_file: None,
+            ellipsis: Vec::new(),
+            ellipsis_indent: false,
+            strict: false,
+            banner: None,
+            header_snippets: false,
+            normalize: false,
+            encoding: None,
+        }
+    }
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn comment(mut self, symbols: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.comment = symbols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn block_comment(mut self, delimiters: Vec<BlockComment>) -> Self {
+        self.block_comment = delimiters;
+        self
+    }
+
+    pub fn lang(mut self, entries: Vec<SyntaxEntry>) -> Self {
+        self.lang = entries;
+        self
+    }
+
+    pub fn lang_file(mut self, table: SyntaxTable) -> Self {
+        self.lang_file = Some(table);
+        self
+    }
+
+    pub fn marker_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.marker_prefix = prefix.into();
+        self
+    }
+
+    pub fn keyword(mut self, renames: Vec<Rename>) -> Self {
+        self.keyword = renames;
+        self
+    }
+
+    pub fn exercise_solution(mut self, solution: bool) -> Self {
+        self.exercise_solution = solution;
+        self
+    }
+
+    pub fn reveal(mut self, tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.reveal = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn reveal_schedule(mut self, schedule: Schedule) -> Self {
+        self.reveal_schedule = Some(schedule);
+        self
+    }
+
+    pub fn define(mut self, symbols: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.define = symbols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn var(mut self, variables: Vec<Variable>) -> Self {
+        self.var = variables;
+        self
+    }
+
+    pub fn var_file(mut self, variables: Variables) -> Self {
+        self.var_file = Some(variables);
+        self
+    }
+
+    pub fn ellipsis(mut self, entries: Vec<EllipsisEntry>) -> Self {
+        self.ellipsis = entries;
+        self
+    }
+
+    pub fn ellipsis_indent(mut self, indent: bool) -> Self {
+        self.ellipsis_indent = indent;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn banner(mut self, text: impl Into<String>) -> Self {
+        self.banner = Some(text.into());
+        self
+    }
+
+    pub fn header_snippets(mut self, snippets: bool) -> Self {
+        self.header_snippets = snippets;
+        self
+    }
+
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: &'static Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// The comment syntax for the file `filepath`. Entries of `lang`
+    /// take precedence over those of `lang_file` and the built-in
+    /// language table. Files of unknown languages use the symbols of
+    /// `comment` and `block_comment`.
+    pub fn comment_syntax(&self, filepath: &Path) -> CommentSyntax {
+        let mut syntax = lookup(&self.lang, filepath)
+            .or_else(|| {
+                self.lang_file
+                    .as_ref()
+                    .and_then(|t| lookup(&t.entries, filepath))
+            })
+            .or_else(|| filepath.extension()?.to_str().and_then(builtin))
+            .unwrap_or_else(|| self.default_syntax());
+        let ext = filepath.extension().and_then(|e| e.to_str());
+        syntax.ellipsis = lookup_ellipsis(&self.ellipsis, ext);
+        syntax
+    }
+
+    /// Test if the solution of an exercise with the (optional) `tag`
+    /// is included. All solutions are included with `exercise_solution`,
+    /// tagged solutions also if the tag is in `reveal` or if its date
+    /// in the `reveal_schedule` has come.
+    pub fn reveals(&self, tag: Option<&str>) -> bool {
+        if self.exercise_solution {
+            return true;
+        }
+        match tag {
+            Some(tag) => {
+                self.reveal.iter().any(|r| r == tag)
+                    || self
+                        .reveal_schedule
+                        .as_ref()
+                        .is_some_and(|s| s.is_due(tag, today()))
+            }
+            None => false,
+        }
+    }
+
+    /// Test if `symbol` is defined for +IF conditions.
+    pub fn is_defined(&self, symbol: &str) -> bool {
+        self.define.iter().any(|d| d == symbol)
+    }
+
+    /// The spelling of the markers given by `marker_prefix` and `keyword`.
+    pub fn keywords(&self) -> Keywords {
+        Keywords::new(&self.marker_prefix, &self.keyword)
+    }
+
+    /// All variables. The built-in variables are `variant` (`public` or
+    /// `solution`) and `year` (the current year). Variables of `var`
+    /// take precedence over those of `var_file`.
+    pub fn variables(&self) -> HashMap<String, String> {
+        let variant = if self.exercise_solution {
+            "solution"
+        } else {
+            "public"
+        };
+        let mut variables = HashMap::from([
+            ("variant".to_string(), variant.to_string()),
+            ("year".to_string(), today().0.to_string()),
+        ]);
+        let file = self.var_file.iter().flat_map(|f| f.entries.iter());
+        for v in file.chain(self.var.iter()) {
+            variables.insert(v.name.clone(), v.value.clone());
+        }
+        variables
+    }
+
+    /// The comment syntax of `comment` and `block_comment`.
+    pub fn default_syntax(&self) -> CommentSyntax {
+        CommentSyntax {
+            line: self.comment.clone(),
+            block: self.block_comment.clone(),
+            ellipsis: lookup_ellipsis(&self.ellipsis, None),
+        }
+    }
+}
+
+/// The configuration of a snippet run, e.g.
+/// ```
+/// use snips::config::{Config, ParseOptions};
+/// let config = Config::new("src")
+///     .snippet_dest_dir("public/snippets")
+///     .file_suffix([".java"])
+///     .options(ParseOptions::new().comment(["//"]));
+/// ```
+/// The command line options of the same name are explained in `Setting`.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub src_dir: PathBuf,
+    pub snippet_dest_dir: PathBuf,
+    pub src_dest_dir: PathBuf,
+    pub file_suffix: Vec<String>,
+    pub dedent: bool,
+    pub dedent_label: Vec<String>,
+    pub indent: usize,
+    pub metadata: bool,
+    pub merge_header: String,
+    pub force_update: bool,
+    pub copy_other_files: bool,
+    pub document: Vec<PathBuf>,
+    pub options: ParseOptions,
+}
+
+impl Config {
+    /// A configuration for the source files in `src_dir` with the
+    /// same defaults as on the command line.
+    pub fn new(src_dir: impl Into<PathBuf>) -> Self {
+        Config {
+            src_dir: src_dir.into(),
+            snippet_dest_dir: PathBuf::from("./snippets"),
+            src_dest_dir: PathBuf::from("./src_dest"),
+            file_suffix: vec![".txt".to_string()],
+            dedent: false,
+            dedent_label: Vec::new(),
+            indent: 0,
+            metadata: false,
+            merge_header: "{file}".to_string(),
+            force_update: false,
+            copy_other_files: false,
+            document: Vec::new(),
+            options: ParseOptions::default(),
+        }
+    }
+
+    pub fn src_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.src_dir = dir.into();
+        self
+    }
+
+    pub fn snippet_dest_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.snippet_dest_dir = dir.into();
+        self
+    }
+
+    pub fn src_dest_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.src_dest_dir = dir.into();
+        self
+    }
+
+    pub fn file_suffix(mut self, suffixes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.file_suffix = suffixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn dedent(mut self, dedent: bool) -> Self {
+        self.dedent = dedent;
+        self
+    }
+
+    pub fn dedent_label(mut self, labels: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.dedent_label = labels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn indent(mut self, spaces: usize) -> Self {
+        self.indent = spaces;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: bool) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn merge_header(mut self, header: impl Into<String>) -> Self {
+        self.merge_header = header.into();
+        self
+    }
+
+    pub fn force_update(mut self, force: bool) -> Self {
+        self.force_update = force;
+        self
+    }
+
+    pub fn copy_other_files(mut self, copy: bool) -> Self {
+        self.copy_other_files = copy;
+        self
+    }
+
+    pub fn document(mut self, documents: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        self.document = documents.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Test if the file `filepath` is processed, i.e. if it ends with
+    /// one of the suffixes of `file_suffix`.
+    pub fn processes(&self, filepath: &Path) -> bool {
+        filepath
+            .to_str()
+            .is_some_and(|f| self.file_suffix.iter().any(|s| f.ends_with(s)))
+    }
+
+    /// Test if the snippet with `label` is dedented. The parts of a
+    /// snippet, e.g. `Slide#2`, are dedented like the snippet.
+    pub fn dedents(&self, label: &str) -> bool {
+        let label = split_part(label).map_or(label, |(base, _)| base);
+        self.dedent || self.dedent_label.iter().any(|l| l == label)
+    }
+}
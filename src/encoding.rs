@@ -0,0 +1,86 @@
+// Module encoding
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/// Number of bytes at the beginning of a file which are searched for
+/// a zero byte to tell binary files from text files.
+const BINARY_PROBE: usize = 8000;
+
+/// The encoding of a text file.
+/// @param encoding the encoding, e.g. UTF-8 or Windows-1252.
+/// @param bom      true if the file starts with a byte order mark.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextEncoding {
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        TextEncoding {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl TextEncoding {
+    /// Decode the `bytes` of a file. A byte order mark determines the
+    /// encoding, otherwise `encoding` if given. Without both, the text
+    /// is UTF-8 if it is valid UTF-8 and Windows-1252 (a superset of
+    /// Latin-1) if not. Returns `None` for a binary file, i.e. a file
+    /// with a zero byte, unless it is UTF-16 by its byte order mark or
+    /// by `encoding`.
+    pub fn decode(
+        bytes: &[u8],
+        encoding: Option<&'static Encoding>,
+    ) -> Result<Option<(String, TextEncoding)>, String> {
+        let utf16 = encoding.is_some_and(|e| e == UTF_16LE || e == UTF_16BE);
+        let (encoding, bom_len) = match Encoding::for_bom(bytes) {
+            Some((encoding, bom_len)) => (encoding, bom_len),
+            None if !utf16 && bytes.iter().take(BINARY_PROBE).any(|&b| b == 0) => return Ok(None),
+            None => match encoding {
+                Some(encoding) => (encoding, 0),
+                None if std::str::from_utf8(bytes).is_ok() => (UTF_8, 0),
+                None => (WINDOWS_1252, 0),
+            },
+        };
+        let (text, malformed) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        if malformed {
+            return Err(format!("File is not valid {}", encoding.name()));
+        }
+        let text_encoding = TextEncoding {
+            encoding,
+            bom: bom_len > 0,
+        };
+        Ok(Some((text.into_owned(), text_encoding)))
+    }
+
+    /// Encode `text` like the file it stems from, including its byte
+    /// order mark.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            let little = self.encoding == UTF_16LE;
+            let units = self.bom.then_some('\u{feff}' as u16).into_iter();
+            for unit in units.chain(text.encode_utf16()) {
+                match little {
+                    true => bytes.extend(unit.to_le_bytes()),
+                    false => bytes.extend(unit.to_be_bytes()),
+                }
+            }
+        } else {
+            if self.bom {
+                bytes.extend(b"\xef\xbb\xbf"); // UTF-8 is the only other encoding with a BOM.
+            }
+            bytes.extend(self.encoding.encode(text).0.iter());
+        }
+        bytes
+    }
+}
+
+/// Read an encoding by its name, e.g. `latin1`, `windows-1252` or
+/// `utf-16le`.
+pub fn read_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes()).ok_or(format!("Unknown encoding: {}", label))
+}
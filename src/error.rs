@@ -0,0 +1,197 @@
+// Module error
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+
+/// The kind of an `Error`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A region is opened again before it was closed, e.g. another +OUT.
+    AlreadyOpen,
+    /// A region is closed which was not opened, e.g. -OUT without +OUT.
+    NotOpen,
+    /// A region is not closed at the end of the file.
+    Unclosed,
+    /// A region is closed while a region opened inside of it is still
+    /// open, e.g. +EXC in a +IN is closed after the -IN.
+    Crossing,
+    /// A comment looks like a directive, e.g. `+EXCSUSBT`, but
+    /// is not known.
+    UnknownDirective,
+    /// A marker is escaped by a comment symbol of `comment` or
+    /// `block_comment` which the language of the file does not use,
+    /// e.g. `// +EXC` in a shell script.
+    IgnoredMarker,
+    /// An attribute of a snippet is not known or has an invalid value.
+    InvalidAttribute,
+    /// A snippet has more lines than its attribute `maxlines` allows.
+    TooLong,
+    /// A file is binary and therefore skipped.
+    Binary,
+    /// A file is not valid in its encoding.
+    Encoding,
+    /// An included snippet or its source file does not exist.
+    Include,
+    /// A file or directory could not be read or written.
+    Io,
+}
+
+/// An error with the location where it occurred. The details are
+/// boxed to keep results small, their fields are accessed through
+/// the error, e.g. `e.kind`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error(Box<Details>);
+
+/// The details of an `Error`. All locations are optional, e.g. there
+/// is no line for a missing directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Details {
+    pub kind: ErrorKind,
+    /// Description of the error without its location.
+    pub message: String,
+    /// The file which caused the error.
+    pub file: Option<PathBuf>,
+    /// Line number (starting at 1) in `file`.
+    pub line: Option<usize>,
+    /// Column (starting at 1) of `marker` in the line.
+    pub column: Option<usize>,
+    /// The offending marker, e.g. `+OUT`.
+    pub marker: Option<String>,
+    /// Line number where the related region was opened.
+    pub opening_line: Option<usize>,
+    /// The text of the line.
+    pub source_line: Option<String>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: &str) -> Self {
+        Error(Box::new(Details {
+            kind,
+            message: message.to_string(),
+            file: None,
+            line: None,
+            column: None,
+            marker: None,
+            opening_line: None,
+            source_line: None,
+        }))
+    }
+
+    /// An I/O error `err` for the file or directory `path`. `message`
+    /// describes what failed.
+    pub fn io(message: &str, path: &Path, err: impl fmt::Display) -> Self {
+        Error::new(ErrorKind::Io, &format!("{}: {}", message, err)).in_file(path)
+    }
+
+    /// Locate the error in line `line_no` with the text `text`.
+    pub fn at(mut self, line_no: usize, text: &str) -> Self {
+        self.line = Some(line_no);
+        self.source_line = Some(text.to_string());
+        self
+    }
+
+    /// Name the offending `marker`. Its column is looked up in the
+    /// line's text.
+    pub fn with_marker(mut self, marker: &str) -> Self {
+        self.column = self
+            .source_line
+            .as_ref()
+            .and_then(|text| text.find(marker).map(|idx| text[..idx].chars().count() + 1));
+        self.marker = Some(marker.to_string());
+        self
+    }
+
+    /// Refer to the line `line_no` where the related region was opened.
+    pub fn opened_at(mut self, line_no: Option<usize>) -> Self {
+        self.opening_line = line_no;
+        self
+    }
+
+    /// Set the file which caused the error.
+    pub fn in_file(mut self, path: &Path) -> Self {
+        self.file = Some(path.to_path_buf());
+        self
+    }
+
+    /// Render the error as a diagnostic with an excerpt of the line, e.g.
+    /// ```text
+    /// error: Another +OUT
+    ///  --> src/Foo.java:4:4
+    ///   |
+    /// 4 | // +OUT
+    ///   |    ^^^^
+    ///   = note: region was opened in line 2
+    /// ```
+    pub fn render(&self) -> String {
+        self.render_as("error")
+    }
+
+    /// Render the error like `render` but as a warning.
+    pub fn render_warning(&self) -> String {
+        self.render_as("warning")
+    }
+
+    /// Render the error as a diagnostic of `severity`, e.g. `error`.
+    fn render_as(&self, severity: &str) -> String {
+        let mut s = format!("{}: {}\n", severity, self.message);
+        let width = self.line.map(|l| l.to_string().len()).unwrap_or(0);
+        let pad = " ".repeat(width);
+        let location = match (&self.file, self.line, self.column) {
+            (Some(f), Some(l), Some(c)) => Some(format!("{}:{}:{}", f.display(), l, c)),
+            (Some(f), Some(l), None) => Some(format!("{}:{}", f.display(), l)),
+            (Some(f), None, _) => Some(format!("{}", f.display())),
+            _ => None,
+        };
+        if let Some(location) = location {
+            s.push_str(&format!("{}--> {}\n", pad, location));
+        }
+        if let (Some(line), Some(text)) = (self.line, &self.source_line) {
+            s.push_str(&format!("{} |\n{} | {}\n", pad, line, text));
+            if let Some(column) = self.column {
+                let len = self.marker.as_ref().map_or(1, |m| m.chars().count());
+                s.push_str(&format!(
+                    "{} | {}{}\n",
+                    pad,
+                    " ".repeat(column - 1),
+                    "^".repeat(len)
+                ));
+            }
+        }
+        if let Some(opening_line) = self.opening_line.filter(|&l| Some(l) != self.line) {
+            s.push_str(&format!(
+                "{} = note: region was opened in line {}\n",
+                pad, opening_line
+            ));
+        }
+        s
+    }
+}
+
+impl Deref for Error {
+    type Target = Details;
+
+    fn deref(&self) -> &Details {
+        &self.0
+    }
+}
+
+impl DerefMut for Error {
+    fn deref_mut(&mut self) -> &mut Details {
+        &mut self.0
+    }
+}
+
+impl fmt::Display for Error {
+    /// Print the error in one line like `src/Foo.java:4: Another +OUT`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}:{}: {}", file.display(), line, self.message),
+            (Some(file), None) => write!(f, "{}: {}", file.display(), self.message),
+            (None, Some(line)) => write!(f, "Line {}: {}", line, self.message),
+            (None, None) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
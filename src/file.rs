@@ -1,70 +1,335 @@
 // Module file
 
+use crate::config::Config;
+use crate::error::Error;
 use crate::parser::Record;
-use crate::util::Setting;
 use crate::DEFAULTLABEL;
 use log::{trace, warn};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
 use std::fs;
-use std::io::Result;
 use std::fs::copy;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 use try_catch::catch;
 
 /// Write all snippets collected in `coll` to their files.
 /// `filepath` is the file to write.
 /// `dir_path` is the current directory for the (nested)
 /// source files.
-/// The environment is controlled by `setting`.
+/// The environment is controlled by `config`.
 pub fn write_files(
     filepath: &Path,
     dir_path: &Path,
     coll: &HashMap<String, Record>,
-    setting: &Setting,
-) {
-    for (label, record) in &*coll {
+    config: &Config,
+) -> Result<(), Error> {
+    for (label, record) in coll {
+        if is_project_label(label) {
+            continue; // Written by write_merged.
+        }
         // println!("\nFile {}", label);
         // println!("{}", record.buffer);
 
         let filename = filepath.file_name().unwrap();
         let filestem = filepath.file_stem().unwrap();
-        let suffix = filepath.extension().unwrap();
+        let suffix = match &record.attributes.lang {
+            Some(lang) => lang.as_str(),
+            None => filepath.extension().unwrap().to_str().unwrap(),
+        };
 
         if label == DEFAULTLABEL {
             // Write snippet:
-            let snippet_file = setting.snippet_dest_dir.join(filename);
-            write_file(&snippet_file, record);
+            let snippet_file = config.snippet_dest_dir.join(filename);
+            write_file(&snippet_file, &record.buffer, record)?;
+            if config.metadata {
+                write_metadata(&snippet_file, filepath, record)?;
+            }
             // Also write full file to src dest:
             let full_file = dir_path.join(filename);
-            write_file(&full_file, record);
+            write_file(&full_file, &record.buffer, record)?;
         } else {
             // Insert label into file's name:
             let ext_filename = format!(
                 "{}_{}.{}",
                 filestem.to_str().unwrap(),
-                label,
-                suffix.to_str().unwrap()
+                file_label(label),
+                suffix
             );
             trace!("Write file: {}", ext_filename);
-            let file = setting.snippet_dest_dir.join(ext_filename);
-            write_file(&file, record);
+            let file = config.snippet_dest_dir.join(ext_filename);
+            let text = snippet_text(label, record, config);
+            write_file(&file, &text, record)?;
+            if config.metadata {
+                write_metadata(&file, filepath, record)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The line endings of a text file.
+/// @param crlf          true if the lines end with `\r\n` (Windows)
+///                      instead of `\n`.
+/// @param final_newline true if the last line ends with a line ending.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineEndings {
+    pub crlf: bool,
+    pub final_newline: bool,
+}
+
+impl LineEndings {
+    /// Detect the line endings of `text` by its first line.
+    pub fn detect(text: &str) -> Self {
+        LineEndings {
+            crlf: text
+                .find('\n')
+                .is_some_and(|idx| text[..idx].ends_with('\r')),
+            final_newline: text.is_empty() || text.ends_with('\n'),
+        }
+    }
+
+    /// The characters which end a line.
+    pub fn eol(&self) -> &'static str {
+        if self.crlf {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    /// Restore the line endings in the snippets of `coll` whose lines
+    /// end with `\n`. Only the whole file loses its final newline.
+    pub fn restore(&self, coll: &mut HashMap<String, Record>) {
+        if !self.final_newline {
+            if let Some(record) = coll.get_mut(DEFAULTLABEL) {
+                if record.buffer.ends_with('\n') {
+                    record.buffer.pop();
+                }
+            }
+        }
+        if self.crlf {
+            for record in coll.values_mut() {
+                record.buffer = record.buffer.replace('\n', "\r\n");
+            }
+        }
+    }
+}
+
+/// The text of the snippet `record` with label `label`. It is dedented
+/// if `config` or the attributes of the snippet ask for it.
+pub fn snippet_text(label: &str, record: &Record, config: &Config) -> String {
+    if config.dedents(label) || record.attributes.dedent {
+        dedent(&record.buffer, config.indent, record.ellipsis())
+    } else {
+        record.buffer.clone()
+    }
+}
+
+/// The fragments of the snippets with project-wide labels, e.g.
+/// `@Observer`. Key: the label. Value: the source files (relative to
+/// the source directory) and their snippet.
+pub type Fragments = BTreeMap<String, Vec<(PathBuf, Record)>>;
+
+/// Test if `label` is project-wide, e.g. `@Observer`. The snippets
+/// with this label in all files are merged into one.
+pub fn is_project_label(label: &str) -> bool {
+    label.starts_with('@')
+}
+
+/// Add the snippets with project-wide labels in `coll` to `fragments`.
+/// `filepath` is the source file relative to the source directory.
+pub fn add_fragments(filepath: &Path, coll: HashMap<String, Record>, fragments: &mut Fragments) {
+    for (label, record) in coll {
+        if is_project_label(&label) {
+            fragments
+                .entry(label)
+                .or_default()
+                .push((filepath.to_path_buf(), record));
         }
     }
 }
 
-fn write_file(filepath: &Path, record: &Record) {
-    fs::write(filepath, record.buffer.as_str()).expect("Unable to write file");
+/// Write the snippets with project-wide labels in `fragments`, e.g.
+/// `@Observer.java`. The fragments of a label are merged in the order
+/// of their source files. Each fragment starts with a header comment
+/// as specified in `config`. With metadata, the sidecar file names
+/// all source files and their line ranges.
+pub fn write_merged(fragments: &Fragments, config: &Config) -> Result<(), Error> {
+    for (label, files) in fragments {
+        let mut files: Vec<&(PathBuf, Record)> = files.iter().collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut text = String::new();
+        let mut meta = String::new();
+        let mut highlights = Vec::new();
+        for (filepath, record) in &files {
+            if !config.merge_header.is_empty() {
+                let header = config
+                    .merge_header
+                    .replace("{file}", &filepath.display().to_string());
+                text.push_str(&config.options.comment_syntax(filepath).comment(&header));
+                // The header line ends like the lines of its fragment:
+                text.push_str(LineEndings::detect(&record.buffer).eol());
+            }
+            meta.push_str(&source_metadata(&config.src_dir.join(filepath), record));
+            // The highlighted lines are numbered relative to the merged snippet:
+            let offset = text.lines().count();
+            highlights.extend(record.highlights.iter().map(|h| h + offset));
+            text.push_str(&snippet_text(label, record, config));
+        }
+        let lang = files[0].1.attributes.lang.as_deref().map(OsStr::new);
+        let filename = match lang.or(files[0].0.extension()) {
+            Some(suffix) => format!("{}.{}", file_label(label), suffix.to_string_lossy()),
+            None => file_label(label),
+        };
+        trace!("Write file: {}", filename);
+        // The merged snippet has the encoding of its first file:
+        let file = config.snippet_dest_dir.join(filename);
+        write_file(&file, &text, &files[0].1)?;
+        if config.metadata {
+            if !highlights.is_empty() {
+                meta.push_str(&format!("highlight: {}\n", line_list(&highlights)));
+            }
+            write_sidecar(&file, meta)?;
+        }
+    }
+    Ok(())
+}
+
+/// The `label` of a snippet as part of a file name. The part of a
+/// snippet, e.g. `Slide#2`, is named like `Slide-2`.
+fn file_label(label: &str) -> String {
+    label.replace('#', "-")
+}
+
+/// Write `text` of the snippet `record` in its encoding to `filepath`.
+fn write_file(filepath: &Path, text: &str, record: &Record) -> Result<(), Error> {
+    fs::write(filepath, record.encoding.encode(text))
+        .map_err(|e| Error::io("File cannot be written", filepath, e))
+}
+
+/// Write the metadata of the snippet file `snippet_file` into a
+/// sidecar file with the additional suffix `.meta`. It contains the
+/// source file `filepath` and the line ranges in the source file
+/// which the snippet stems from, one range per code snippet, e.g.
+/// ```text
+/// source: src/Foo.java
+/// lines: 4-6,10-10
+/// firstnumber: 4
+/// highlight: 2-3
+/// ```
+/// The (optional) highlighted lines are numbered relative to the snippet.
+fn write_metadata(snippet_file: &Path, filepath: &Path, record: &Record) -> Result<(), Error> {
+    let mut meta = source_metadata(filepath, record);
+    if !record.highlights.is_empty() {
+        meta.push_str(&format!("highlight: {}\n", line_list(&record.highlights)));
+    }
+    write_sidecar(snippet_file, meta)
+}
+
+/// The source file `filepath` of the snippet `record`, its line
+/// ranges and the number of its first line as metadata.
+fn source_metadata(filepath: &Path, record: &Record) -> String {
+    let ranges: Vec<String> = record
+        .ranges
+        .iter()
+        .map(|(first, last)| format!("{}-{}", first, last))
+        .collect();
+    let mut meta = format!(
+        "source: {}\nlines: {}\n",
+        filepath.display(),
+        ranges.join(",")
+    );
+    if let Some((first, _)) = record.ranges.first() {
+        meta.push_str(&format!("firstnumber: {}\n", first));
+    }
+    meta
+}
+
+/// Write the metadata `meta` into the sidecar file of `snippet_file`.
+fn write_sidecar(snippet_file: &Path, meta: String) -> Result<(), Error> {
+    let mut meta_file = snippet_file.as_os_str().to_owned();
+    meta_file.push(".meta");
+    let meta_file = PathBuf::from(meta_file);
+    fs::write(&meta_file, meta).map_err(|e| Error::io("File cannot be written", &meta_file, e))
+}
+
+/// Format ascending line numbers like `[2, 3, 4, 7]` as `2-4,7`.
+fn line_list(lines: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &line in lines {
+        match ranges.last_mut() {
+            Some(range) if range.1 + 1 == line => range.1 = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    let ranges: Vec<String> = ranges
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}-{}", first, last)
+            }
+        })
+        .collect();
+    ranges.join(",")
+}
+
+/// Remove the common leading whitespace of all lines in `text` and
+/// indent them by `indent` spaces instead. Empty lines and `ellipsis`
+/// lines are not considered for the common whitespace. Indented
+/// ellipsis lines are dedented like the code. Line endings are kept.
+pub fn dedent(text: &str, indent: usize, ellipsis: Option<&str>) -> String {
+    let is_code = |line: &&str| !line.trim().is_empty() && Some(line.trim()) != ellipsis;
+    // Find the longest whitespace prefix which all lines share:
+    let common = text
+        .lines()
+        .filter(is_code)
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .reduce(|a, b| {
+            let len = a
+                .chars()
+                .zip(b.chars())
+                .take_while(|(x, y)| x == y)
+                .map(|(x, _)| x.len_utf8())
+                .sum();
+            &a[..len]
+        })
+        .unwrap_or_default();
+    let spaces = " ".repeat(indent);
+    let mut result = String::new();
+    for line in text.split_inclusive('\n') {
+        // Keep the line ending:
+        let eol = &line[line.trim_end_matches(['\r', '\n']).len()..];
+        let line = &line[..line.len() - eol.len()];
+        if is_code(&line) {
+            result.push_str(&spaces);
+            result.push_str(&line[common.len()..]);
+        } else if let Some(rest) = line
+            .strip_prefix(common)
+            .filter(|rest| rest.len() < line.len() && !rest.trim().is_empty())
+        {
+            // An indented ellipsis:
+            result.push_str(&spaces);
+            result.push_str(rest);
+        } else {
+            result.push_str(line.trim_start());
+        }
+        result.push_str(eol);
+    }
+    result
 }
 
 /// Test if the file to be processed (represented by `filepath`) is modified,
 /// i.e. newer than the file(s) being created. The time stamp of the files is compared.
 /// `src_dest_path` is the path to the current source destination folder.
-/// `setting` contains, among other things, the path to the snippets folder.
+/// `config` contains, among other things, the path to the snippets folder.
 /// Returns true if time stamp of file is newer than
 /// processed file or if the processed file
 /// does not exist yet. Returns false if not.
-pub fn test_if_modified(filepath: &Path, src_dest_path: &Path, setting: &Setting) -> bool {
-    if setting.force_update {
+pub fn test_if_modified(filepath: &Path, src_dest_path: &Path, config: &Config) -> bool {
+    if config.force_update {
         return true; // Update always.
     }
 
@@ -74,7 +339,7 @@ pub fn test_if_modified(filepath: &Path, src_dest_path: &Path, setting: &Setting
             let src_mod_time = fs::metadata(filepath)?.modified()?;
             // Target files:
             let filename = filepath.file_name().unwrap();
-            let snippet_filepath = setting.snippet_dest_dir.join(filename);
+            let snippet_filepath = config.snippet_dest_dir.join(filename);
             let snippet_file = fs::metadata(snippet_filepath);
             let src_dest_filepath = src_dest_path.join(filename);
             let src_dest_file = fs::metadata(src_dest_filepath);
@@ -94,7 +359,7 @@ pub fn test_if_modified(filepath: &Path, src_dest_path: &Path, setting: &Setting
     }
 }
 
-pub fn copy_file(filepath: &Path, src_dest_path: &Path, _setting: &Setting) -> Result<u64> {
+pub fn copy_file(filepath: &Path, src_dest_path: &Path, _config: &Config) -> io::Result<u64> {
     let dest = src_dest_path.join(filepath.file_name().unwrap());
     copy(filepath, dest)?;
     Ok(0)
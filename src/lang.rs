@@ -0,0 +1,256 @@
+// Module lang
+
+use crate::parser::ELLIPSIS;
+use crate::util::BlockComment;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// An entry of the built-in language table.
+struct Language {
+    /// File extensions without dot.
+    exts: &'static [&'static str],
+    /// Line comment symbols.
+    line: &'static [&'static str],
+    /// Block comment delimiters.
+    block: &'static [(&'static str, &'static str)],
+}
+
+/// Built-in comment syntax of common languages.
+const LANGUAGES: &[Language] = &[
+    Language {
+        exts: &[
+            "java", "c", "h", "cpp", "hpp", "cc", "cs", "js", "ts", "kt", "scala", "go", "rs",
+            "swift", "dart", "groovy", "php",
+        ],
+        line: &["//"],
+        block: &[("/*", "*/")],
+    },
+    Language {
+        exts: &["css", "scss"],
+        line: &[],
+        block: &[("/*", "*/")],
+    },
+    Language {
+        exts: &[
+            "py", "r", "sh", "bash", "rb", "pl", "yaml", "yml", "toml", "jl",
+        ],
+        line: &["#"],
+        block: &[],
+    },
+    Language {
+        exts: &["sql", "lua", "ada"],
+        line: &["--"],
+        block: &[],
+    },
+    Language {
+        exts: &["hs"],
+        line: &["--"],
+        block: &[("{-", "-}")],
+    },
+    Language {
+        exts: &["html", "htm", "xml", "xhtml", "svg"],
+        line: &[],
+        block: &[("<!--", "-->")],
+    },
+    Language {
+        exts: &["tex", "sty", "cls"],
+        line: &["%"],
+        block: &[],
+    },
+    Language {
+        exts: &["ml", "mli", "pas"],
+        line: &[],
+        block: &[("(*", "*)")],
+    },
+    Language {
+        exts: &["lisp", "clj", "scm", "el", "asm"],
+        line: &[";"],
+        block: &[],
+    },
+];
+
+/// The comment syntax of a language, i.e. the symbols which
+/// escape a marker.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommentSyntax {
+    /// Line comment symbols, e.g. `//`.
+    pub line: Vec<String>,
+    /// Block comment delimiters, e.g. `/*` and `*/`.
+    pub block: Vec<BlockComment>,
+    /// Line which indicates omitted lines if it is not a comment.
+    pub ellipsis: Option<String>,
+}
+
+impl CommentSyntax {
+    /// Comment out `text` with the first line comment symbol or,
+    /// if there is none, with the first block comment delimiters.
+    pub fn comment(&self, text: &str) -> String {
+        match (self.line.first(), self.block.first()) {
+            (Some(symbol), _) => format!("{} {}", symbol, text),
+            (None, Some(b)) => format!("{} {} {}", b.open, text, b.close),
+            (None, None) => text.to_string(),
+        }
+    }
+
+    /// The line which indicates omitted lines, by default `...` as a
+    /// comment, e.g. `// ...` or `# ...`. `none` omits these lines.
+    pub fn ellipsis(&self) -> String {
+        match &self.ellipsis {
+            Some(ellipsis) => ellipsis.clone(),
+            None => self.comment(ELLIPSIS),
+        }
+    }
+}
+
+impl FromStr for CommentSyntax {
+    type Err = String;
+
+    /// Read a comma separated list of comment symbols like `"//,/* */"`.
+    /// An entry with a space is a pair of block comment delimiters,
+    /// any other entry is a line comment symbol.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut syntax = CommentSyntax::default();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            if entry.contains(char::is_whitespace) {
+                syntax.block.push(BlockComment::from_str(entry)?);
+            } else {
+                syntax.line.push(entry.to_string());
+            }
+        }
+        Ok(syntax)
+    }
+}
+
+/// Comment syntax for files with the extension `ext`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxEntry {
+    pub ext: String,
+    pub syntax: CommentSyntax,
+}
+
+impl FromStr for SyntaxEntry {
+    type Err = String;
+
+    /// Read an entry like `"java=//,/* */"`. A leading dot of the
+    /// extension is ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((ext, comments)) => Ok(SyntaxEntry {
+                ext: normalize_ext(ext.trim()),
+                syntax: CommentSyntax::from_str(comments)?,
+            }),
+            None => Err(format!("Expected suffix=comments but found: {}", s)),
+        }
+    }
+}
+
+/// Comment syntax of the language with file extension `ext` from the
+/// built-in table. Returns `None` if the language is unknown.
+pub fn builtin(ext: &str) -> Option<CommentSyntax> {
+    let ext = normalize_ext(ext);
+    LANGUAGES
+        .iter()
+        .find(|l| l.exts.contains(&ext.as_str()))
+        .map(|l| CommentSyntax {
+            line: l.line.iter().map(|s| s.to_string()).collect(),
+            block: l
+                .block
+                .iter()
+                .map(|(open, close)| BlockComment {
+                    open: open.to_string(),
+                    close: close.to_string(),
+                })
+                .collect(),
+            ellipsis: None,
+        })
+}
+
+/// The line which indicates omitted lines in files with the
+/// extension `ext`, or in all files if there is no extension.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EllipsisEntry {
+    pub ext: Option<String>,
+    pub text: String,
+}
+
+impl FromStr for EllipsisEntry {
+    type Err = String;
+
+    /// Read an entry like `"py=# ..."` or `"..."`. Text before the
+    /// first `=` is an extension if it is a single word.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ext, text) = match s.split_once('=') {
+            Some((ext, text))
+                if !ext.trim_start_matches('.').is_empty()
+                    && ext
+                        .trim_start_matches('.')
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric()) =>
+            {
+                (Some(normalize_ext(ext)), text)
+            }
+            _ => (None, s),
+        };
+        match text.trim() {
+            "" => Err(format!("Expected an ellipsis but found: {}", s)),
+            text => Ok(EllipsisEntry {
+                ext,
+                text: text.to_string(),
+            }),
+        }
+    }
+}
+
+/// Find the ellipsis for files with the extension `ext` in `entries`.
+/// Entries without extension apply to all files.
+pub fn lookup_ellipsis(entries: &[EllipsisEntry], ext: Option<&str>) -> Option<String> {
+    let ext = ext.map(normalize_ext);
+    let find = |ext: Option<&String>| {
+        entries
+            .iter()
+            .rev() // Later entries override earlier ones.
+            .find(|e| e.ext.as_ref() == ext)
+            .map(|e| e.text.clone())
+    };
+    ext.as_ref()
+        .and_then(|e| find(Some(e)))
+        .or_else(|| find(None))
+}
+
+/// Find the comment syntax for the file `filepath` in `entries`.
+pub fn lookup(entries: &[SyntaxEntry], filepath: &Path) -> Option<CommentSyntax> {
+    let ext = normalize_ext(filepath.extension()?.to_str()?);
+    entries
+        .iter()
+        .rev() // Later entries override earlier ones.
+        .find(|e| e.ext == ext)
+        .map(|e| e.syntax.clone())
+}
+
+/// Entries read from a syntax file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxTable {
+    pub entries: Vec<SyntaxEntry>,
+}
+
+impl SyntaxTable {
+    /// Read a syntax file with one `suffix=comments` entry per line.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn read(filename: &str) -> Result<Self, String> {
+        let path = PathBuf::from(filename);
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read syntax file {}: {}", path.display(), e))?;
+        let entries = content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(SyntaxEntry::from_str)
+            .collect::<Result<_, _>>()?;
+        Ok(SyntaxTable { entries })
+    }
+}
+
+fn normalize_ext(ext: &str) -> String {
+    ext.trim_start_matches('.').to_lowercase()
+}
@@ -1,146 +1,294 @@
+pub mod config;
+pub mod encoding;
+pub mod error;
 pub mod file;
+pub mod lang;
 pub mod parser;
 pub mod util;
+pub mod weave;
 
-use crate::file::{copy_file, test_if_modified, write_files};
-use crate::parser::parse;
-use crate::util::Setting;
+pub use crate::config::{Config, ParseOptions};
+pub use crate::parser::parse_str;
+
+use crate::encoding::TextEncoding;
+use crate::error::{Error, ErrorKind};
+use crate::file::{
+    add_fragments, copy_file, test_if_modified, write_files, write_merged, Fragments, LineEndings,
+};
+use crate::parser::{parse_lines, Record};
 use log::{debug, info, warn};
+use std::collections::HashMap;
 use std::fs;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub const DEFAULTLABEL: &str = "x8gfz4hd"; // crazy string as an ID for default label
 
-/// Scan all files as specified in `setting`.
-pub fn scan(setting: &Setting) -> Result<(), String> {
+/// Scan all files as specified in `config`.
+pub fn scan(config: &Config) -> Result<(), Error> {
     // First, we need to check if all directories are valid and available.
 
     // Verify that source directory exists:
-    let metadata = fs::metadata(&setting.src_dir);
-    if let Err(e) = metadata {
-        let err = format!(
-            "Error: Source directory {} does not exist.\n{}",
-            &setting.src_dir.display(),
-            e
-        );
-        return Err(err);
-    }
-    if !setting.src_dir.is_dir() {
-        return Err(format!(
-            "Error: Source directory {} is not a directory.",
-            &setting.src_dir.display()
+    if let Err(e) = fs::metadata(&config.src_dir) {
+        return Err(Error::io(
+            "Source directory does not exist",
+            &config.src_dir,
+            e,
         ));
     }
+    if !config.src_dir.is_dir() {
+        return Err(
+            Error::new(ErrorKind::Io, "Source directory is not a directory")
+                .in_file(&config.src_dir),
+        );
+    }
 
     // Verify that snippet directory is available:
-    if !setting.snippet_dest_dir.is_dir() {
+    if !config.snippet_dest_dir.is_dir() {
         warn!(
             "Create snippets destination directory: {}",
-            &setting.snippet_dest_dir.display()
+            &config.snippet_dest_dir.display()
         );
-        if let Err(e) = fs::create_dir(&setting.snippet_dest_dir) {
-            return Err(format!(
-                "Error: snippets destination directory {} could not be created.\n{}",
-                &setting.src_dir.display(),
-                e
+        if let Err(e) = fs::create_dir_all(&config.snippet_dest_dir) {
+            return Err(Error::io(
+                "Snippets destination directory could not be created",
+                &config.snippet_dest_dir,
+                e,
             ));
         }
     }
 
     // Verify that src_dest directory is available:
-    if !setting.src_dest_dir.is_dir() {
+    if !config.src_dest_dir.is_dir() {
         warn!(
             "Create source destination directory: {}",
-            &setting.src_dest_dir.display()
+            &config.src_dest_dir.display()
         );
-        if let Err(e) = fs::create_dir(&setting.src_dest_dir) {
-            return Err(format!(
-                "Error: Source destination directory {} could not be created.\n{}",
-                &setting.src_dest_dir.display(),
-                e
+        if let Err(e) = fs::create_dir_all(&config.src_dest_dir) {
+            return Err(Error::io(
+                "Source destination directory could not be created",
+                &config.src_dest_dir,
+                e,
             ));
         }
     }
 
     info!("Scanning...");
-    if let Err(e) = scan_rec(&setting.src_dir, &setting.src_dest_dir, &setting) {
-        return Err(format!("Scanning files failed with error: {}", e));
+    let mut merge = Merge::default();
+    scan_rec(&config.src_dir, &config.src_dest_dir, config, &mut merge)?;
+    // The merged snippets are only stale if a file was modified. Then the
+    // unmodified files are parsed again for their fragments. Their
+    // warnings were logged when they were modified.
+    if merge.stale {
+        for file in &merge.unmodified {
+            let coll = match parse_file(file, config, &mut Vec::new()) {
+                Err(e) if e.kind == ErrorKind::Binary => continue,
+                coll => coll?,
+            };
+            let relative = file.strip_prefix(&config.src_dir).unwrap_or(file);
+            add_fragments(relative, coll, &mut merge.fragments);
+        }
+        write_merged(&merge.fragments, config)?;
     }
     info!("... done");
     Ok(())
 }
 
+/// The snippets with project-wide labels of a scan.
+/// @param fragments  fragments of the parsed files.
+/// @param unmodified files which were not parsed as they are not modified.
+/// @param stale      true if a file was parsed, i.e. the merged snippets
+///                   may be stale.
+#[derive(Default)]
+struct Merge {
+    fragments: Fragments,
+    unmodified: Vec<PathBuf>,
+    stale: bool,
+}
+
 /// Scan the files in directory `src_dir` recursively. `src_dir` is the root
-/// directory as specified in `setting` when `scan_rec` is called for the first time.
+/// directory as specified in `config` when `scan_rec` is called for the first time.
 /// `src_dest_dir` is the destination source directory. It is the root
-/// directory as specified in `setting`when `scan_rec` is called for the first time.
-/// `setting` contains the environment for the scan.
-fn scan_rec(src_dir: &Path, src_dest_dir: &Path, setting: &Setting) -> Result<(), String> {
+/// directory as specified in `config`when `scan_rec` is called for the first time.
+/// `config` contains the environment for the scan.
+/// The snippets with project-wide labels are added to `merge`.
+fn scan_rec(
+    src_dir: &Path,
+    src_dest_dir: &Path,
+    config: &Config,
+    merge: &mut Merge,
+) -> Result<(), Error> {
     debug!(" {}", src_dir.display());
 
     // Recursively scan other directories:
-    for entry in fs::read_dir(src_dir).unwrap() {
-        let entry = entry.unwrap();
+    let entries =
+        fs::read_dir(src_dir).map_err(|e| Error::io("Directory cannot be read", src_dir, e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| Error::io("Directory cannot be read", src_dir, e))?;
         let next_dir_or_file = entry.path();
         if next_dir_or_file.is_dir() {
             let dir = next_dir_or_file; // for better reading...
                                         // Add next directory to dir path:
-            let ext_dir_path = src_dest_dir.join(&dir.file_name().unwrap());
+            let ext_dir_path = src_dest_dir.join(dir.file_name().unwrap());
             // Make sure nested source destination dirs exist (actually it should):
             if !ext_dir_path.is_dir() {
                 if let Err(e) = fs::create_dir(&ext_dir_path) {
-                    return Err(format!(
-                        "Error: Nested source destination directory {} could not be created.\n{}",
-                        ext_dir_path.display(),
-                        e
+                    return Err(Error::io(
+                        "Nested source destination directory could not be created",
+                        &ext_dir_path,
+                        e,
                     ));
                 }
             }
-            scan_rec(&dir, &ext_dir_path, setting)?;
+            scan_rec(&dir, &ext_dir_path, config, merge)?;
         } else {
             // file
             let file = next_dir_or_file; // for better reading...
-                                         // Test if file matches suffix:
-            if let Some(filename) = file.to_str() {
-                // Check if file ends with provided suffixes:
-                let file_match = setting.file_suffix.iter().any(|s| filename.ends_with(s));
-                if file_match {
-                    // Process file. Check if source files are modified:
-                    if test_if_modified(file.as_path(), &src_dest_dir, setting) {
-                        info!(" {}", file.display());
-                        parse_write(file.as_path(), &src_dest_dir, setting)?;
-                    } else {
-                        debug!(" {} not modified", file.display());
-                    }
-                } else {
-                    if setting.copy_other_files {
-                        // Skip or just copy...?
-                        if let Err(_) = copy_file(file.as_path(), &src_dest_dir, setting) {
-                            return Err(format!("Copying file failed: {}", file.display()));
-                        }
-                        debug!(" copied {}", file.display());
-                    } else {
-                        debug!(" skipped {}", file.display());
+            if config.processes(&file) {
+                // Process file. Check if source files are modified:
+                if !test_if_modified(file.as_path(), src_dest_dir, config) {
+                    debug!(" {} not modified", file.display());
+                    merge.unmodified.push(file);
+                    continue;
+                }
+                info!(" {}", file.display());
+                let coll = match parse_write(file.as_path(), src_dest_dir, config) {
+                    Err(e) if e.kind == ErrorKind::Binary => {
+                        warn!("{}", e.render_warning());
+                        continue;
                     }
+                    coll => coll?,
+                };
+                merge.stale = true;
+                let relative = file.strip_prefix(&config.src_dir).unwrap_or(&file);
+                add_fragments(relative, coll, &mut merge.fragments);
+            } else if config.copy_other_files {
+                // Skip or just copy...?
+                if let Err(e) = copy_file(file.as_path(), src_dest_dir, config) {
+                    return Err(Error::io("Copying file failed", &file, e));
                 }
+                debug!(" copied {}", file.display());
+            } else {
+                debug!(" skipped {}", file.display());
             }
         }
     }
     Ok(())
 }
 
-pub fn parse_write(filepath: &Path, src_dest_dir: &Path, setting: &Setting) -> Result<(), String> {
+/// Parse the file `filepath` and write its snippets. Errors are
+/// located in `filepath`, warnings are logged. Returns the snippets.
+pub fn parse_write(
+    filepath: &Path,
+    src_dest_dir: &Path,
+    config: &Config,
+) -> Result<HashMap<String, Record>, Error> {
+    let coll = parse_logged(filepath, config)?;
+    write_files(filepath, src_dest_dir, &coll, config)?;
+    Ok(coll)
+}
+
+/// Like `parse_file` but warnings are logged.
+pub(crate) fn parse_logged(
+    filepath: &Path,
+    config: &Config,
+) -> Result<HashMap<String, Record>, Error> {
+    let mut warnings = Vec::new();
+    let coll = parse_file(filepath, config, &mut warnings);
+    for w in warnings {
+        warn!("{}", w.render_warning());
+    }
+    coll
+}
+
+/// Parse the file `filepath` with the comment syntax of its language.
+/// Warnings are added to `warnings`. Errors and warnings are located
+/// in `filepath`. The snippets keep the encoding and the line endings
+/// of the file unless `config` normalizes the line endings. Binary
+/// files are errors of kind `ErrorKind::Binary`.
+pub(crate) fn parse_file(
+    filepath: &Path,
+    config: &Config,
+    warnings: &mut Vec<Error>,
+) -> Result<HashMap<String, Record>, Error> {
     // Make vector of the lines in the text file:
-    let file = File::open(filepath).unwrap();
-    let reader = BufReader::new(&file);
-    let v: Vec<String> = reader.lines().map(|e| e.unwrap()).collect();
-    let lines: Vec<&str> = v.iter().map(|s| s as &str).collect();
-
-    // Parse the content of the file:
-    let coll = parse(&lines, setting)?;
-    write_files(filepath, src_dest_dir, &coll, setting);
+    let bytes = fs::read(filepath).map_err(|e| Error::io("File cannot be read", filepath, e))?;
+    let (text, encoding) = match TextEncoding::decode(&bytes, config.options.encoding) {
+        Ok(Some(decoded)) => decoded,
+        Ok(None) => {
+            return Err(Error::new(ErrorKind::Binary, "Binary file is skipped").in_file(filepath))
+        }
+        Err(message) => return Err(Error::new(ErrorKind::Encoding, &message).in_file(filepath)),
+    };
+    let lines: Vec<&str> = text.lines().collect();
+
+    let syntax = config.options.comment_syntax(filepath);
+    let mut file_warnings = Vec::new();
+    let coll = parse_lines(&lines, &syntax, &config.options, &mut file_warnings);
+    warnings.extend(file_warnings.into_iter().map(|w| w.in_file(filepath)));
+    let mut coll = coll.map_err(|e| e.in_file(filepath))?;
+    if !config.options.normalize {
+        LineEndings::detect(&text).restore(&mut coll);
+    }
+    for record in coll.values_mut() {
+        record.encoding = encoding;
+    }
+    Ok(coll)
+}
+
+/// The result of `check`.
+/// @param files    number of checked files.
+/// @param errors   errors of all files.
+/// @param warnings warnings of all files.
+#[derive(Debug)]
+pub struct Report {
+    pub files: usize,
+    pub errors: Vec<Error>,
+    pub warnings: Vec<Error>,
+}
+
+/// Check the markers of all files as specified in `config` without
+/// writing any files. Unlike `scan`, the errors of all files are
+/// collected in the returned `Report`.
+pub fn check(config: &Config) -> Result<Report, Error> {
+    if !config.src_dir.is_dir() {
+        return Err(
+            Error::new(ErrorKind::Io, "Source directory is not a directory")
+                .in_file(&config.src_dir),
+        );
+    }
+    let mut report = Report {
+        files: 0,
+        errors: Vec::new(),
+        warnings: Vec::new(),
+    };
+    check_rec(&config.src_dir, config, &mut report)?;
+    Ok(report)
+}
+
+/// Check the files in directory `src_dir` recursively and add their
+/// errors to `report`. The files are checked in alphabetical order.
+fn check_rec(src_dir: &Path, config: &Config, report: &mut Report) -> Result<(), Error> {
+    let mut paths = fs::read_dir(src_dir)
+        .and_then(|entries| {
+            entries
+                .map(|e| e.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| Error::io("Directory cannot be read", src_dir, e))?;
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            check_rec(&path, config, report)?;
+        } else if config.processes(&path) {
+            debug!(" {}", path.display());
+            report.files += 1;
+            match parse_file(&path, config, &mut report.warnings) {
+                Err(e) if e.kind == ErrorKind::Binary => report.warnings.push(e),
+                Err(e) => report.errors.push(e),
+                Ok(_) => (),
+            }
+        }
+    }
     Ok(())
 }
 
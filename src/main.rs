@@ -1,7 +1,10 @@
 use clap::Parser;
 use log::*;
-use snips::scan;
-use snips::util::Setting;
+use snips::config::Config;
+use snips::util::{Command, Setting};
+use snips::weave::weave;
+use snips::{check, scan};
+use std::process;
 
 fn main() {
     // https://rust-lang-nursery.github.io/rust-cookbook/cli/arguments.html
@@ -18,8 +21,34 @@ fn main() {
         .init()
         .unwrap();
 
-    if let Err(e) = scan(&setting) {
-        error!("Error: snips failed.");
-        error!("{}", e);
+    let command = setting.command;
+    let config = Config::from(setting);
+    let success = match command {
+        Command::Scan => scan(&config).map(|_| true),
+        Command::Weave => weave(&config).map(|_| true),
+        Command::Check => check(&config).map(|report| {
+            for w in &report.warnings {
+                warn!("{}", w.render_warning());
+            }
+            for e in &report.errors {
+                error!("{}", e.render());
+            }
+            println!(
+                "Checked {} files: {} error(s), {} warning(s).",
+                report.files,
+                report.errors.len(),
+                report.warnings.len()
+            );
+            report.errors.is_empty()
+        }),
+    };
+    match success {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            error!("Error: snips failed.");
+            error!("{}", e.render());
+            process::exit(1);
+        }
     }
 }
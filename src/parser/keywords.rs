@@ -0,0 +1,152 @@
+// Spelling of the markers
+
+use std::str::FromStr;
+
+/// All directives, i.e. markers in a comment line.
+pub const DIRECTIVES: [&str; 19] = [
+    "+IN",
+    "-IN",
+    "+OUT",
+    "-OUT",
+    "+EXC",
+    "-EXC",
+    "+EXCSUBST",
+    "-EXCSUBST",
+    "+SOLUTION",
+    "+VAR",
+    "-VAR",
+    "+ORIGINAL",
+    "+IF",
+    "-IF",
+    "+ELSE",
+    "+HL",
+    "-HL",
+    "+HEADER",
+    "-HEADER",
+];
+
+/// All inline markers, i.e. markers in a trailing comment.
+pub const INLINE: [&str; 4] = ["@IN", "@OUT", "@EXC", "@HL"];
+
+/// The spelling of the markers. By default, a marker is spelled like
+/// its keyword, e.g. `+EXC`.
+/// @param prefix prefix of all markers, e.g. `snips:` for `snips:+IN`.
+/// @param words  words of the markers and the keywords they stand for,
+///               e.g. `@@EXC` for `+EXC`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keywords {
+    prefix: String,
+    words: Vec<(String, &'static str)>,
+}
+
+impl Keywords {
+    /// The markers with the common `prefix` where the keywords
+    /// in `renames` are spelled differently.
+    pub fn new(prefix: &str, renames: &[Rename]) -> Self {
+        let words = DIRECTIVES
+            .iter()
+            .chain(INLINE.iter())
+            .map(|&keyword| {
+                let word = renames
+                    .iter()
+                    .rev() // The last rename wins.
+                    .find(|r| r.keyword == keyword)
+                    .map_or(keyword, |r| r.word.as_str());
+                (word.to_string(), keyword)
+            })
+            .collect();
+        Keywords {
+            prefix: prefix.to_string(),
+            words,
+        }
+    }
+
+    /// Read the marker `word`, e.g. `+EXC:week3`. Returns the keyword
+    /// (`+EXC`) and the optional tag (`week3`) or `None` if `word`
+    /// is no marker.
+    pub fn read(&self, word: &str) -> Option<(&'static str, Option<String>)> {
+        let (name, tag) = split_tag(word.strip_prefix(self.prefix.as_str())?);
+        let keyword = self.words.iter().find(|(w, _)| *w == name)?.1;
+        Some((keyword, tag))
+    }
+
+    /// The marker for `keyword` as it is spelled in the files.
+    pub fn spelling(&self, keyword: &str) -> String {
+        let word = self
+            .words
+            .iter()
+            .find(|(_, k)| *k == keyword)
+            .map_or(keyword, |(w, _)| w.as_str());
+        format!("{}{}", self.prefix, word)
+    }
+
+    /// Test if `word` looks like a directive. This is a word with
+    /// the prefix which, without a prefix, starts like `+WORD` or `-WORD`.
+    /// Returns the word without the prefix.
+    pub fn looks_like_directive<'a>(&self, word: &'a str) -> Option<&'a str> {
+        let name = word.strip_prefix(self.prefix.as_str())?;
+        if !self.prefix.is_empty() {
+            return Some(name);
+        }
+        let rest = name.strip_prefix(['+', '-'])?;
+        let (rest, _) = split_tag(rest);
+        if rest.is_empty() || !rest.chars().all(char::is_alphabetic) {
+            return None; // This is no directive, e.g. `// -1`.
+        }
+        Some(name)
+    }
+
+    /// The words (without prefix) and keywords of all directives.
+    pub fn directive_words(&self) -> impl Iterator<Item = (&str, &'static str)> {
+        self.words
+            .iter()
+            .filter(|(_, k)| DIRECTIVES.contains(k))
+            .map(|(w, k)| (w.as_str(), *k))
+    }
+}
+
+impl Default for Keywords {
+    fn default() -> Self {
+        Keywords::new("", &[])
+    }
+}
+
+/// Split a marker like `+EXC:week3` into the keyword (`+EXC`)
+/// and the optional tag (`week3`).
+fn split_tag(marker: &str) -> (&str, Option<String>) {
+    match marker.split_once(':') {
+        Some((keyword, tag)) if !tag.is_empty() => (keyword, Some(tag.to_string())),
+        _ => (marker, None),
+    }
+}
+
+/// Another spelling of a keyword, e.g. `@@EXC` for `+EXC`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rename {
+    pub keyword: &'static str,
+    pub word: String,
+}
+
+impl FromStr for Rename {
+    type Err = String;
+
+    /// Read a rename from a string like `"+EXC=@@EXC"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (keyword, word) = s
+            .split_once('=')
+            .ok_or(format!("Expected keyword=word but found: {}", s))?;
+        let keyword = DIRECTIVES
+            .iter()
+            .chain(INLINE.iter())
+            .find(|&&k| k == keyword.trim())
+            .ok_or(format!("Unknown keyword: {}", keyword))?;
+        let word = word.trim();
+        if word.is_empty() || word.contains(char::is_whitespace) || word.contains(':') {
+            return Err(format!("Invalid word for {}: {}", keyword, word));
+        }
+        Ok(Rename {
+            keyword,
+            word: word.to_string(),
+        })
+    }
+}
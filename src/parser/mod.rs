@@ -1,26 +1,84 @@
 // Parser
 
+mod keywords;
 mod tests; // Unit tests are in other file.
 
+pub use keywords::{Keywords, Rename};
+
 // Issues: none
 
-use crate::util::Setting;
+use crate::config::ParseOptions;
+use crate::encoding::TextEncoding;
+use crate::error::{Error, ErrorKind};
+use crate::file::LineEndings;
+use crate::lang::CommentSyntax;
+use crate::util::expand;
 use crate::DEFAULTLABEL;
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use std::collections::HashMap;
 
+/// The line which indicates omitted lines in a snippet.
+pub const ELLIPSIS: &str = "...";
+
 /// Parse a vector of text lines (`lines`) and extract snippets.
-/// The environment is specified in `setting`.
+/// The environment is specified in `options`.
 /// The snippets are returned in a hash map where the keys
 /// are the snippet labels and the processed text file is contained
 /// as the `Record` value.
-pub fn parse(lines: &Vec<&str>, setting: &Setting) -> Result<HashMap<String, Record>, String> {
+/// Markers are escaped by the comment symbols of `options`.
+pub fn parse(lines: &Vec<&str>, options: &ParseOptions) -> Result<HashMap<String, Record>, Error> {
+    parse_with_syntax(lines, &options.default_syntax(), options)
+}
+
+/// Like `parse` but for a `text` instead of its lines. The snippets
+/// keep the line endings of `text` unless `options` normalizes them.
+pub fn parse_str(text: &str, options: &ParseOptions) -> Result<HashMap<String, Record>, Error> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut coll = parse(&lines, options)?;
+    if !options.normalize {
+        LineEndings::detect(text).restore(&mut coll);
+    }
+    Ok(coll)
+}
+
+/// Like `parse` but markers are escaped by the comment symbols
+/// in `syntax`, usually the ones of the file's language.
+/// Warnings are logged.
+pub fn parse_with_syntax(
+    lines: &Vec<&str>,
+    syntax: &CommentSyntax,
+    options: &ParseOptions,
+) -> Result<HashMap<String, Record>, Error> {
+    let mut warnings = Vec::new();
+    let coll = parse_lines(lines, syntax, options, &mut warnings);
+    for w in warnings {
+        warn!("{}", w.render_warning());
+    }
+    coll
+}
+
+/// Like `parse_with_syntax` but warnings, e.g. about unknown
+/// directives, are added to `warnings`. With `strict` options they
+/// are errors instead.
+pub fn parse_lines(
+    lines: &Vec<&str>,
+    syntax: &CommentSyntax,
+    options: &ParseOptions,
+    warnings: &mut Vec<Error>,
+) -> Result<HashMap<String, Record>, Error> {
     let no_lines = lines.len(); // of the the source file
+    let keywords = options.keywords(); // spelling of the markers
+    let defaults = Attributes {
+        ellipsis: Some(syntax.ellipsis()),
+        ..Attributes::default()
+    };
     let mut mode = Mode {
-        out: false,
-        exc: false,
-        excsubst: false,
-        var: false,
+        out: None,
+        exc: None,
+        excsubst: None,
+        var: None,
+        hl: None,
+        header: None,
     };
 
     // Output collector.
@@ -29,10 +87,33 @@ pub fn parse(lines: &Vec<&str>, setting: &Setting) -> Result<HashMap<String, Rec
     let mut coll: HashMap<String, Record> = HashMap::new();
 
     let mut quiet = false; // if true, lines are omitted.
-    let mut exercise_quiet = false; // if true, lines are not omitted.
+    let mut exercise_quiet = false; // if true, solution lines are omitted.
+
+    // Nested +IF regions. Lines are omitted unless all of them are active.
+    let mut conditions: Vec<Condition> = Vec::new();
+    // True if the replacement lines of a +VAR are read, which end
+    // with +ORIGINAL. These lines are printed without comment.
+    let mut replacement = false;
+    // Some if the stub lines of a +EXCSUBST are read, which end with
+    // +SOLUTION. If true, these lines are printed without comment.
+    let mut stub: Option<bool> = None;
+
+    // The banner, e.g. a license, as comment lines in this language:
+    let banner: Option<Vec<String>> = options.banner.as_deref().map(|text| {
+        text.lines()
+            .map(|line| syntax.comment(line).trim_end().to_string())
+            .collect()
+    });
+    let mut header_seen = false; // true if the file has a +HEADER region.
+
+    // The symbols of `comment` and `block_comment` if the language of
+    // this file has other comment symbols. Markers escaped by them are
+    // ignored, which is reported:
+    let ignored =
+        Some(options.default_syntax()).filter(|s| s.line != syntax.line || s.block != syntax.block);
 
     // This is the default snippet for extracting the whole source code.
-    start(DEFAULTLABEL.to_string(), &mut coll);
+    start(DEFAULTLABEL.to_string(), 0, &mut coll);
 
     // Process line by line...
     for (counter, line) in lines.iter().enumerate() {
@@ -41,250 +122,985 @@ pub fn parse(lines: &Vec<&str>, setting: &Setting) -> Result<HashMap<String, Rec
         // Show the line and its number.
         trace!("{}. {}", counter + 1, line);
 
+        // An error of kind `kind` at `marker` in this line:
+        let error = |kind, message: &str, marker: &str| {
+            Error::new(kind, message)
+                .at(line_no, line)
+                .with_marker(&keywords.spelling(marker))
+        };
+
+        // The indentation of an ellipsis at this line:
+        let layout = Layout {
+            defaults: &defaults,
+            indent: match options.ellipsis_indent {
+                true => &line[..line.len() - line.trim_start().len()],
+                false => "",
+            },
+        };
+
+        // Are we in the active branch of all +IF regions?
+        let in_branch = conditions.iter().all(|c| c.active);
+
         // Parse the next token:
-        let _ = match read_token(&line, &setting) {
+        match read_token(line, syntax, &keywords) {
             // see if this line is a token.
-            Some(Token::RegularToken { label, start: true }) => {
-                debug!("  +IN {}", label); // begin of a code snippet.
-                                           // label is moved into hash map:
-                start(label.to_string(), &mut coll);
-                if coll.get(&label).unwrap().counter <= 1 && line_no > 1 {
-                    // Print ... but not at the beginning of the file
-                    // or when ... was printed at the end of a code snippet.
-                    coll.get_mut(&label).unwrap().buffer.push_str("...\n");
-                }
-                ()
+            Some(Token::RegularToken {
+                label,
+                start: true,
+                attributes,
+            }) => {
+                debug!("  +IN {} {:?}", label, attributes); // begin of a code snippet.
+                let attributes = match Attributes::read(&attributes) {
+                    _ if attributes.is_empty() => None,
+                    Ok(mut attributes) => {
+                        attributes.ellipsis.get_or_insert_with(|| syntax.ellipsis());
+                        Some(attributes)
+                    }
+                    Err((word, message)) => {
+                        let e = Error::new(ErrorKind::InvalidAttribute, &message)
+                            .at(line_no, line)
+                            .with_marker(&word);
+                        if options.strict {
+                            return Err(e);
+                        }
+                        warnings.push(e);
+                        None
+                    }
+                };
+                open_snippet(label, line_no, attributes, &layout, &mut coll);
             }
             Some(Token::RegularToken {
                 label,
                 start: false,
+                ..
             }) => {
                 debug!("  -IN {}", label); // end of a code snippet.
-                end(label.to_string(), &mut coll)?;
-                if line_no < no_lines {
-                    // Print ... but not at the end of the file.
-                    coll.get_mut(&label).unwrap().buffer.push_str("...\n");
+                let opened = coll.get(&label).filter(|r| r.active).map(|r| r.opened);
+                let regions = open_regions(&mode, &conditions, &coll);
+                check_nesting(opened, &regions)
+                    .map_err(|e| e.at(line_no, line).with_marker(&keywords.spelling("-IN")))?;
+                close_snippet(label, line_no, no_lines, &layout, &mut coll)
+                    .map_err(|e| e.at(line_no, line).with_marker(&keywords.spelling("-IN")))?;
+            }
+            Some(Token::InlineToken { text, marker }) => {
+                debug!("  {:?}", marker);
+                let printed = !quiet && !exercise_quiet && in_branch;
+                match marker {
+                    Inline::In(label) => {
+                        if let Some(r) = coll.get(&label).filter(|r| r.active) {
+                            let message = format!("Snippet {} is already open", label);
+                            return Err(error(ErrorKind::AlreadyOpen, &message, "@IN")
+                                .opened_at(Some(r.opened)));
+                        }
+                        // A code snippet of just this line, if it is printed:
+                        if printed {
+                            open_snippet(label.clone(), line_no, None, &layout, &mut coll);
+                            print(&mut coll, &text, line_no, mode.hl.is_some());
+                            close_snippet(label, line_no, no_lines, &layout, &mut coll).map_err(
+                                |e| e.at(line_no, line).with_marker(&keywords.spelling("@IN")),
+                            )?;
+                        }
+                    }
+                    Inline::Out => (), // omit this line.
+                    Inline::Exc(tag) => {
+                        if printed && options.reveals(tag.as_deref()) {
+                            print(&mut coll, &text, line_no, mode.hl.is_some());
+                        }
+                    }
+                    Inline::Hl => {
+                        if printed {
+                            print(&mut coll, &text, line_no, true);
+                        }
+                    }
                 }
-                ()
             }
             Some(Token::QuietToken { start: true }) => {
-                if mode.out {
-                    return Err(format!("Line {}: Another +OUT", line_no));
+                if mode.out.is_some() {
+                    return Err(
+                        error(ErrorKind::AlreadyOpen, "Another +OUT", "+OUT").opened_at(mode.out)
+                    );
                 }
-                mode.out = true;
+                mode.out = Some(line_no);
                 quiet = true; // start to omit output.
-                ()
             }
             Some(Token::QuietToken { start: false }) => {
-                if !mode.out {
-                    return Err(format!("Line {}: -OUT without preceding +OUT", line_no));
+                if mode.out.is_none() {
+                    return Err(error(
+                        ErrorKind::NotOpen,
+                        "-OUT without preceding +OUT",
+                        "-OUT",
+                    ));
                 }
-                mode.out = false;
+                let regions = open_regions(&mode, &conditions, &coll);
+                check_nesting(mode.out, &regions)
+                    .map_err(|e| e.at(line_no, line).with_marker(&keywords.spelling("-OUT")))?;
+                mode.out = None;
                 quiet = false; // end omitting output.
-                ()
             }
-            Some(Token::ExerciseToken { start: true }) => {
-                debug!("  +EXC");
-                if mode.exc {
-                    return Err(format!("Line {}: Another +EXC", line_no));
+            Some(Token::ExerciseToken { start: true, tag }) => {
+                debug!("  +EXC {:?}", tag);
+                if mode.exc.is_some() {
+                    return Err(
+                        error(ErrorKind::AlreadyOpen, "Another +EXC", "+EXC").opened_at(mode.exc)
+                    );
                 }
-                mode.exc = true;
-                exercise_quiet = true; // start to omit output in exercise mod.
-                ()
+                mode.exc = Some(line_no);
+                // start to omit output unless the solution is revealed:
+                exercise_quiet = !options.reveals(tag.as_deref());
             }
-            Some(Token::ExerciseToken { start: false }) => {
+            Some(Token::ExerciseToken { start: false, .. }) => {
                 debug!("  -EXC");
-                if !mode.exc {
-                    return Err(format!("Line {}: -EXC without preceding +EXC", line_no));
+                if mode.exc.is_none() {
+                    return Err(error(
+                        ErrorKind::NotOpen,
+                        "-EXC without preceding +EXC",
+                        "-EXC",
+                    ));
                 }
-                mode.exc = false;
+                let regions = open_regions(&mode, &conditions, &coll);
+                check_nesting(mode.exc, &regions)
+                    .map_err(|e| e.at(line_no, line).with_marker(&keywords.spelling("-EXC")))?;
+                mode.exc = None;
                 exercise_quiet = false; // end omitting output in exercise mod.
-                ()
             }
             Some(Token::ReplaceToken {
                 s: text,
                 start: true,
             }) => {
-                if mode.var {
-                    return Err(format!("Line {}: Another +VAR", line_no));
+                if mode.var.is_some() {
+                    return Err(
+                        error(ErrorKind::AlreadyOpen, "Another +VAR", "+VAR").opened_at(mode.var)
+                    );
                 }
-                mode.var = true;
-                for r in coll.values_mut() {
-                    // all records
-                    if r.active {
-                        r.buffer.push_str(&text);
-                        r.buffer.push('\n');
+                mode.var = Some(line_no);
+                match text {
+                    Some(text) => {
+                        if in_branch {
+                            print(&mut coll, &text, line_no, mode.hl.is_some());
+                        }
                     }
+                    None => replacement = true, // replacement lines follow.
                 }
                 quiet = true; // prevents to output next line.
-                ()
             }
-            Some(Token::ReplaceToken { s: _, start: false }) => {
-                if !mode.var {
-                    return Err(format!("Line {}: -VAR without preceding +VAR", line_no));
+            Some(Token::ReplaceToken { start: false, .. }) => {
+                if mode.var.is_none() {
+                    return Err(error(
+                        ErrorKind::NotOpen,
+                        "-VAR without preceding +VAR",
+                        "-VAR",
+                    ));
+                }
+                if replacement {
+                    return Err(error(
+                        ErrorKind::NotOpen,
+                        "-VAR without preceding +ORIGINAL",
+                        "-VAR",
+                    ));
                 }
-                mode.var = false;
+                let regions = open_regions(&mode, &conditions, &coll);
+                check_nesting(mode.var, &regions)
+                    .map_err(|e| e.at(line_no, line).with_marker(&keywords.spelling("-VAR")))?;
+                mode.var = None;
                 quiet = false; // end marker, output is allowed again.
-                ()
             }
             Some(Token::ExerciseReplaceToken {
                 s: text,
                 start: true,
+                tag,
             }) => {
-                debug!("  +EXCSUBST");
-                if mode.excsubst {
-                    return Err(format!("Line {}: Another +EXCSUBST", line_no));
+                debug!("  +EXCSUBST {:?}", tag);
+                if mode.excsubst.is_some() {
+                    return Err(
+                        error(ErrorKind::AlreadyOpen, "Another +EXCSUBST", "+EXCSUBST")
+                            .opened_at(mode.excsubst),
+                    );
                 }
-                mode.excsubst = true;
-                if !setting.exercise_solution && !quiet {
-                    for r in coll.values_mut() {
-                        if r.active {
-                            r.buffer.push_str(&format!("{}\n", &text));
+                mode.excsubst = Some(line_no);
+                let substitute = !options.reveals(tag.as_deref()) && !quiet;
+                match text {
+                    Some(text) if substitute => {
+                        if in_branch {
+                            print(&mut coll, &text, line_no, mode.hl.is_some());
                         }
                     }
+                    Some(_) => (),
+                    // Stub lines follow. They are skipped with solutions.
+                    None => stub = Some(substitute),
+                }
+                if substitute {
                     quiet = true; // prevents to output next line.
-                    ()
                 }
             }
-            Some(Token::ExerciseReplaceToken { s: _, start: false }) => {
+            Some(Token::ExerciseReplaceToken { start: false, .. }) => {
                 debug!("  -EXCSUBST");
-                if !mode.excsubst {
-                    return Err(format!(
-                        "Line {}: -EXCSUBST without preceding +EXCSUBST",
-                        line_no
+                if mode.excsubst.is_none() {
+                    return Err(error(
+                        ErrorKind::NotOpen,
+                        "-EXCSUBST without preceding +EXCSUBST",
+                        "-EXCSUBST",
+                    ));
+                }
+                if stub.is_some() {
+                    return Err(error(
+                        ErrorKind::NotOpen,
+                        "-EXCSUBST without preceding +SOLUTION",
+                        "-EXCSUBST",
                     ));
                 }
-                mode.excsubst = false;
+                let regions = open_regions(&mode, &conditions, &coll);
+                check_nesting(mode.excsubst, &regions).map_err(|e| {
+                    e.at(line_no, line)
+                        .with_marker(&keywords.spelling("-EXCSUBST"))
+                })?;
+                mode.excsubst = None;
                 quiet = false; // end marker, output is allowed again.
-                ()
             }
-            _ => {
-                if !quiet && (setting.exercise_solution || !exercise_quiet) {
-                    // omit lines when in quiet mode.
-                    // Store line for every code snippet label...
-                    for r in coll.values_mut() {
-                        if r.active {
-                            r.buffer.push_str(&line);
-                            r.buffer.push('\n');
+            Some(Token::SolutionToken) => {
+                debug!("  +SOLUTION");
+                if stub.is_none() {
+                    return Err(error(
+                        ErrorKind::NotOpen,
+                        "+SOLUTION without preceding +EXCSUBST",
+                        "+SOLUTION",
+                    ));
+                }
+                stub = None; // The solution follows.
+            }
+            Some(Token::OriginalToken) => {
+                debug!("  +ORIGINAL");
+                if !replacement {
+                    return Err(error(
+                        ErrorKind::NotOpen,
+                        "+ORIGINAL without preceding +VAR",
+                        "+ORIGINAL",
+                    ));
+                }
+                replacement = false; // The replaced lines follow.
+            }
+            Some(Token::ConditionalToken {
+                symbol,
+                start: true,
+            }) => {
+                debug!("  +IF {}", symbol);
+                // A symbol with a leading ! is negated:
+                let active = match symbol.strip_prefix('!') {
+                    Some(s) => !options.is_defined(s),
+                    None => options.is_defined(&symbol),
+                };
+                conditions.push(Condition {
+                    symbol,
+                    active,
+                    alternative: None,
+                    line: line_no,
+                });
+            }
+            Some(Token::AlternativeToken) => {
+                debug!("  +ELSE");
+                let opened = conditions.last().map(|c| c.line);
+                let regions = open_regions(&mode, &conditions, &coll);
+                check_nesting(opened, &regions)
+                    .map_err(|e| e.at(line_no, line).with_marker(&keywords.spelling("+ELSE")))?;
+                match conditions.last_mut() {
+                    Some(c) if c.alternative.is_none() => {
+                        c.active = !c.active;
+                        c.alternative = Some(line_no);
+                    }
+                    Some(c) => {
+                        return Err(error(ErrorKind::AlreadyOpen, "Another +ELSE", "+ELSE")
+                            .opened_at(c.alternative))
+                    }
+                    None => {
+                        return Err(error(
+                            ErrorKind::NotOpen,
+                            "+ELSE without preceding +IF",
+                            "+ELSE",
+                        ))
+                    }
+                }
+            }
+            Some(Token::ConditionalToken {
+                symbol,
+                start: false,
+            }) => {
+                debug!("  -IF {}", symbol);
+                let opened = conditions.last().map(|c| c.line);
+                let regions = open_regions(&mode, &conditions, &coll);
+                check_nesting(opened, &regions)
+                    .map_err(|e| e.at(line_no, line).with_marker(&keywords.spelling("-IF")))?;
+                match conditions.pop() {
+                    // The symbol after -IF is optional but must match:
+                    Some(c) if !symbol.is_empty() && symbol != c.symbol => {
+                        let message = format!("Crossing regions: +IF {} is still open", c.symbol);
+                        return Err(
+                            error(ErrorKind::Crossing, &message, "-IF").opened_at(Some(c.line))
+                        );
+                    }
+                    Some(_) => (),
+                    None => {
+                        return Err(error(
+                            ErrorKind::NotOpen,
+                            "-IF without preceding +IF",
+                            "-IF",
+                        ))
+                    }
+                }
+            }
+            Some(Token::HeaderToken { start: true }) => {
+                debug!("  +HEADER");
+                if mode.header.is_some() {
+                    return Err(error(ErrorKind::AlreadyOpen, "Another +HEADER", "+HEADER")
+                        .opened_at(mode.header));
+                }
+                mode.header = Some(line_no);
+                header_seen = true;
+                if let Some(banner) = &banner {
+                    coll.get_mut(DEFAULTLABEL).unwrap().push_banner(banner);
+                }
+            }
+            Some(Token::HeaderToken { start: false }) => {
+                debug!("  -HEADER");
+                if mode.header.is_none() {
+                    return Err(error(
+                        ErrorKind::NotOpen,
+                        "-HEADER without preceding +HEADER",
+                        "-HEADER",
+                    ));
+                }
+                let regions = open_regions(&mode, &conditions, &coll);
+                check_nesting(mode.header, &regions).map_err(|e| {
+                    e.at(line_no, line)
+                        .with_marker(&keywords.spelling("-HEADER"))
+                })?;
+                mode.header = None;
+            }
+            Some(Token::HighlightToken { start: true }) => {
+                debug!("  +HL");
+                if mode.hl.is_some() {
+                    return Err(
+                        error(ErrorKind::AlreadyOpen, "Another +HL", "+HL").opened_at(mode.hl)
+                    );
+                }
+                mode.hl = Some(line_no);
+            }
+            Some(Token::HighlightToken { start: false }) => {
+                debug!("  -HL");
+                if mode.hl.is_none() {
+                    return Err(error(
+                        ErrorKind::NotOpen,
+                        "-HL without preceding +HL",
+                        "-HL",
+                    ));
+                }
+                let regions = open_regions(&mode, &conditions, &coll);
+                check_nesting(mode.hl, &regions)
+                    .map_err(|e| e.at(line_no, line).with_marker(&keywords.spelling("-HL")))?;
+                mode.hl = None;
+            }
+            None => {
+                if let Some((word, message)) = unknown_directive(line, syntax, &keywords) {
+                    let e = Error::new(ErrorKind::UnknownDirective, &message)
+                        .at(line_no, line)
+                        .with_marker(word);
+                    if options.strict {
+                        return Err(e);
+                    }
+                    warnings.push(e);
+                }
+                if let Some(word) = ignored
+                    .as_ref()
+                    .and_then(|s| ignored_marker(line, s, &keywords))
+                {
+                    let message = format!(
+                        "Marker {} is ignored, its comment symbol is not used for this language",
+                        word
+                    );
+                    let e = Error::new(ErrorKind::IgnoredMarker, &message)
+                        .at(line_no, line)
+                        .with_marker(word);
+                    if options.strict {
+                        return Err(e);
+                    }
+                    warnings.push(e);
+                }
+                if let Some(printed) = stub.or(replacement.then_some(true)) {
+                    if printed && in_branch {
+                        let text = uncomment(line, syntax).unwrap_or_else(|| line.to_string());
+                        print(&mut coll, &text, line_no, mode.hl.is_some());
+                    }
+                    continue; // This line is no regular line.
+                }
+                if !quiet && !exercise_quiet && in_branch {
+                    if mode.header.is_some() {
+                        // The file's header is no part of a snippet. The
+                        // banner replaces it in the whole file:
+                        if banner.is_none() {
+                            let record = coll.get_mut(DEFAULTLABEL).unwrap();
+                            record.push(line, line_no, mode.hl.is_some());
                         }
+                    } else {
+                        // omit lines when in quiet mode.
+                        // Store line for every code snippet label...
+                        print(&mut coll, line, line_no, mode.hl.is_some());
                     }
                 }
-                ()
             }
-        };
+        }
+    }
+    // All regions must be closed at the end of the file:
+    if let Some(region) = open_regions(&mode, &conditions, &coll).first() {
+        return Err(Error::new(
+            ErrorKind::Unclosed,
+            &format!("{} is not closed", region.marker),
+        )
+        .at(region.line, lines[region.line - 1])
+        .with_marker(&keywords.spelling(region.keyword))
+        .opened_at(Some(region.line)));
     }
     end(DEFAULTLABEL.to_string(), &mut coll)?; // end default code snippet.
+    combine_parts(&mut coll);
+    for (label, record) in coll.iter_mut() {
+        if record.attributes.trim {
+            record.trim();
+        }
+        // The ellipsis lines do not count:
+        let count = record
+            .buffer
+            .lines()
+            .filter(|&l| !record.is_ellipsis(l))
+            .count();
+        // The parts of a snippet are checked as the combined snippet:
+        let maxlines = record
+            .attributes
+            .maxlines
+            .filter(|_| split_part(label).is_none());
+        if let Some(maxlines) = maxlines.filter(|&n| count > n) {
+            let message = format!(
+                "Snippet {} has {} lines, more than maxlines={}",
+                label, count, maxlines
+            );
+            let e = Error::new(ErrorKind::TooLong, &message)
+                .at(record.opened, lines[record.opened - 1])
+                .with_marker(&keywords.spelling("+IN"));
+            if options.strict {
+                return Err(e);
+            }
+            warnings.push(e);
+        }
+    }
+    if let Some(banner) = &banner {
+        for (label, record) in coll.iter_mut() {
+            if label == DEFAULTLABEL {
+                if !header_seen {
+                    record.prepend_banner(banner);
+                }
+            } else if options.header_snippets {
+                record.prepend_banner(banner);
+            }
+        }
+    }
+    // Expand variables like ${course} in all printed lines:
+    let variables = options.variables();
+    for record in coll.values_mut() {
+        record.buffer = expand(&record.buffer, &variables);
+    }
     Ok(coll)
 }
 
+/// Split a label like `Slide#2` into the label of the snippet (`Slide`)
+/// and the number of the part (2). Returns `None` if `label` is no part.
+pub fn split_part(label: &str) -> Option<(&str, usize)> {
+    let (base, part) = label.rsplit_once('#')?;
+    Some((base, part.parse().ok()?))
+}
+
+/// Combine the numbered parts of a snippet in `coll`, e.g. `Slide#1` and
+/// `Slide#2`, into the snippet (`Slide`) in the order of their numbers.
+/// Fragments of the snippet without a number come first. The parts
+/// themselves are kept.
+fn combine_parts(coll: &mut HashMap<String, Record>) {
+    let mut parts: Vec<(String, usize, String)> = coll
+        .keys()
+        .filter_map(|label| {
+            split_part(label).map(|(base, part)| (base.to_string(), part, label.clone()))
+        })
+        .collect();
+    parts.sort();
+    for (base, _, label) in &parts {
+        // The first part opens the snippet:
+        let mut combined = coll.remove(base).unwrap_or_else(|| Record {
+            attributes: coll[label].attributes.clone(),
+            opened: coll[label].opened,
+            ..Record::new(false)
+        });
+        combined.append(&coll[label]);
+        coll.insert(base.clone(), combined);
+    }
+}
+
 /// Read the next token in the text file's `line`.
-/// The environment is controlled by `setting`:
-fn read_token<'a>(line: &'a str, setting: &'a Setting) -> Option<Token> {
-    // Test if `text` is an escape comment according to
-    // the settings as specified in `setting`.
-    let is_comment_escape = |text: &str| {
-        setting.comment.iter().any(|s| text == s)
-    };
+/// Markers are escaped by the comment symbols in `syntax`
+/// and spelled as in `keywords`.
+fn read_token<'a>(line: &'a str, syntax: &CommentSyntax, keywords: &Keywords) -> Option<Token> {
+    // A trailing comment may contain an inline marker:
+    if let Some(token) = read_inline_token(line, syntax, keywords) {
+        return Some(token);
+    }
 
-    // let line = line.clone();
-    let tokens: Vec<&str> = line.trim().split_whitespace().collect();
+    // The text of the comment which may contain a marker:
+    let body = marker_body(line, syntax)?;
+    let tokens: Vec<&str> = body.split_whitespace().collect();
+    // Exercises may carry a tag, e.g. `+EXCSUBST:week3`:
+    let (keyword, tag) = keywords.read(tokens.first()?)?;
+    if tag.is_some() && !matches!(keyword, "+EXC" | "-EXC" | "+EXCSUBST" | "-EXCSUBST") {
+        return None;
+    }
 
-    // Rest of line for EXC* tokens:
+    // Rest of line for EXCSUBST and VAR tokens. It is indented like the
+    // marker line unless a number after the marker sets the indentation:
     let rest_of_line = || {
-        let indent: i32 = tokens[2].parse().unwrap_or_default();
-        // Truncate first three tokens and fill them with spaces:
-        let mut spaces = if indent == 0 {
-            String::new() // empty string
-        } else {
-            let c: Vec<char> = (1..indent).into_iter().map(|_| ' ').collect();
-            String::from_iter(c)
+        // Skip a token at the beginning of `text` and the space after it:
+        let skip = |text: &'a str, token: &str| {
+            let rest = &text.trim_start()[token.len()..];
+            rest.strip_prefix(char::is_whitespace).unwrap_or(rest)
         };
-        // Find rest of line:
-        let p = format!("{} {}", tokens[1], tokens[2]);
-        let idx = line.find(&p).unwrap() + p.chars().count() + 1;
-        spaces.push_str(&line[idx..]); // Add rest of line.
-        spaces
+        let rest = skip(body, tokens[0]);
+        match tokens[1].parse::<usize>() {
+            Ok(indent) => format!("{}{}", " ".repeat(indent), skip(rest, tokens[1])),
+            Err(_) => format!("{}{}", indentation(line), rest),
+        }
     };
 
-    if tokens.len() == 2 {
-        if is_comment_escape(tokens[0]) && tokens[1] == "+OUT" {
+    // The tag of an EXC may also be the next word, e.g. `+EXC week3`:
+    if (keyword == "+EXC" || keyword == "-EXC") && tokens.len() <= 2 {
+        return Some(Token::ExerciseToken {
+            start: keyword == "+EXC",
+            tag: tag.or_else(|| tokens.get(1).map(|t| t.to_string())),
+        });
+    }
+
+    if tokens.len() == 1 {
+        if keyword == "+ELSE" {
+            return Some(Token::AlternativeToken);
+        }
+        if keyword == "-IF" {
+            return Some(Token::ConditionalToken {
+                symbol: "".to_string(),
+                start: false,
+            });
+        }
+        if keyword == "+HL" {
+            return Some(Token::HighlightToken { start: true });
+        }
+        if keyword == "-HL" {
+            return Some(Token::HighlightToken { start: false });
+        }
+        if keyword == "+OUT" {
             return Some(Token::QuietToken { start: true });
         }
-        if is_comment_escape(tokens[0]) && tokens[1] == "-OUT" {
+        if keyword == "-OUT" {
             return Some(Token::QuietToken { start: false });
         }
-        if is_comment_escape(tokens[0]) && tokens[1] == "+EXC" {
-            return Some(Token::ExerciseToken { start: true });
+        if keyword == "-EXCSUBST" {
+            return Some(Token::ExerciseReplaceToken {
+                s: None,
+                start: false,
+                tag,
+            });
+        }
+        if keyword == "+SOLUTION" {
+            return Some(Token::SolutionToken);
         }
-        if is_comment_escape(tokens[0]) && tokens[1] == "-EXC" {
-            return Some(Token::ExerciseToken { start: false });
+        if keyword == "+ORIGINAL" {
+            return Some(Token::OriginalToken);
         }
-        if is_comment_escape(tokens[0]) && tokens[1] == "-EXCSUBST" {
+        // Replacements without text are followed by commented lines:
+        if keyword == "+EXCSUBST" {
             return Some(Token::ExerciseReplaceToken {
-                s: "".to_string(),
-                start: false,
+                s: None,
+                start: true,
+                tag,
             });
         }
-        if is_comment_escape(tokens[0]) && tokens[1] == "-HEADER" {
+        if keyword == "+VAR" {
             return Some(Token::ReplaceToken {
-                s: "".to_string(),
-                start: false,
+                s: None,
+                start: true,
             });
         }
-        if is_comment_escape(tokens[0]) && tokens[1] == "-VAR" {
+        if keyword == "+HEADER" {
+            return Some(Token::HeaderToken { start: true });
+        }
+        if keyword == "-HEADER" {
+            return Some(Token::HeaderToken { start: false });
+        }
+        if keyword == "-VAR" {
             return Some(Token::ReplaceToken {
-                s: "".to_string(),
+                s: None,
                 start: false,
             });
         }
     }
 
-    if tokens.len() >= 3 {
-        if is_comment_escape(tokens[0]) && tokens[1] == "+IN" {
+    if tokens.len() == 2 {
+        if keyword == "+IF" {
+            return Some(Token::ConditionalToken {
+                symbol: tokens[1].to_string(),
+                start: true,
+            });
+        }
+        if keyword == "-IF" {
+            return Some(Token::ConditionalToken {
+                symbol: tokens[1].to_string(),
+                start: false,
+            });
+        }
+    }
+
+    if tokens.len() >= 2 {
+        if keyword == "+IN" {
             return Some(Token::RegularToken {
-                label: tokens[2].to_string(),
+                label: tokens[1].to_string(),
                 start: true,
+                attributes: tokens[2..].iter().map(|t| t.to_string()).collect(),
             });
         }
-        if is_comment_escape(tokens[0]) && tokens[1] == "-IN" {
+        if keyword == "-IN" {
             return Some(Token::RegularToken {
-                label: tokens[2].to_string(),
+                label: tokens[1].to_string(),
                 start: false,
+                attributes: Vec::new(),
             });
         }
-        if is_comment_escape(tokens[0]) && tokens[1] == "+HEADER" {
-            let idx = tokens[0].len() + tokens[1].len(); // Truncate first letters.
-            let s = line[idx..].to_string();
-            return Some(Token::ReplaceToken { s: s, start: true });
-        }
-        if is_comment_escape(tokens[0]) && tokens[1] == "+VAR" {
+        if keyword == "+VAR" {
             return Some(Token::ReplaceToken {
-                s: rest_of_line(),
+                s: Some(rest_of_line()),
                 start: true,
             });
         }
-        if is_comment_escape(tokens[0]) && tokens[1] == "+EXCSUBST" {
+        if keyword == "+EXCSUBST" {
             return Some(Token::ExerciseReplaceToken {
-                s: rest_of_line(),
+                s: Some(rest_of_line()),
                 start: true,
+                tag,
             });
         }
     }
     None
 }
 
-fn start(label: String, coll: &mut HashMap<String, Record>) {
-    if !coll.contains_key(&label) {
-        coll.insert(label, Record::new(true));
-    } else {
-        coll.get_mut(&label).unwrap().active = true;
-        coll.get_mut(&label).unwrap().counter += 1;
+/// Check if `line` is a marker escaped by the comment symbols in
+/// `syntax`, which are not used for the language of the file. Returns
+/// the word of the marker, e.g. `+EXC`.
+fn ignored_marker<'a>(
+    line: &'a str,
+    syntax: &CommentSyntax,
+    keywords: &Keywords,
+) -> Option<&'a str> {
+    read_token(line, syntax, keywords)?;
+    match read_inline_token(line, syntax, keywords) {
+        Some(_) => line.split_whitespace().find(|w| keywords.read(w).is_some()),
+        None => marker_body(line, syntax)?.split_whitespace().next(),
+    }
+}
+
+/// Check if `line` looks like a directive, i.e. a comment (as specified
+/// in `syntax`) starting with a word like `+WORD` or `-WORD`, although
+/// `read_token` did not recognize it. Returns the word and a message
+/// which suggests the intended directive, if any. Markers are spelled
+/// as in `keywords`.
+fn unknown_directive<'a>(
+    line: &'a str,
+    syntax: &CommentSyntax,
+    keywords: &Keywords,
+) -> Option<(&'a str, String)> {
+    let body = marker_body(line, syntax)?;
+    let word = body.split_whitespace().next()?;
+    let name = keywords.looks_like_directive(word)?;
+    if keywords.read(word).is_some() {
+        return Some((word, format!("Malformed directive {}", body.trim())));
+    }
+    // Suggest the most similar directive if it differs only slightly:
+    let (name, _) = name.split_once(':').unwrap_or((name, ""));
+    let upper = name.to_uppercase();
+    let suggestion = keywords
+        .directive_words()
+        .map(|(w, keyword)| (distance(&upper, &w.to_uppercase()), keyword))
+        .filter(|&(dist, _)| dist <= 2)
+        .min_by_key(|&(dist, _)| dist);
+    // The marker with its prefix but without a tag:
+    let marker = &word[..word.len() - keywords.looks_like_directive(word)?.len() + name.len()];
+    let message = match suggestion {
+        Some((_, keyword)) => format!(
+            "Unknown directive {}, did you mean {}?",
+            marker,
+            keywords.spelling(keyword)
+        ),
+        None => format!("Unknown directive {}", marker),
+    };
+    Some((word, message))
+}
+
+/// The edit (Levenshtein) distance between `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Distances of the prefixes of `a` (so far) to all prefixes of `b`:
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(x != *y);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Read an inline marker in a trailing comment of `line`, e.g.
+/// `int a = 1; // @IN Slide`. The marker applies to this line only.
+/// Markers are escaped by the comment symbols in `syntax`
+/// and spelled as in `keywords`.
+fn read_inline_token(line: &str, syntax: &CommentSyntax, keywords: &Keywords) -> Option<Token> {
+    // All comments in line: position of the comment symbol and text.
+    let mut comments: Vec<(usize, &str)> = Vec::new();
+    for s in &syntax.line {
+        for (idx, _) in line.match_indices(s.as_str()) {
+            comments.push((idx, &line[idx + s.len()..]));
+        }
+    }
+    let text = line.trim_end();
+    for b in &syntax.block {
+        if let Some(t) = text.strip_suffix(b.close.as_str()) {
+            if let Some(idx) = t.rfind(b.open.as_str()) {
+                comments.push((idx, &t[idx + b.open.len()..]));
+            }
+        }
+    }
+
+    comments.into_iter().find_map(|(idx, comment)| {
+        let code = line[..idx].trim_end();
+        if code.trim().is_empty() {
+            return None; // This is no trailing comment.
+        }
+        let tokens: Vec<&str> = comment.split_whitespace().collect();
+        let (keyword, tag) = keywords.read(tokens.first()?)?;
+        let marker = match (keyword, tokens.len()) {
+            ("@IN", 2) => Inline::In(tokens[1].to_string()),
+            ("@OUT", 1) => Inline::Out,
+            ("@EXC", 1 | 2) => Inline::Exc(tag.or_else(|| tokens.get(1).map(|t| t.to_string()))),
+            ("@HL", 1) => Inline::Hl,
+            _ => return None,
+        };
+        Some(Token::InlineToken {
+            text: code.to_string(),
+            marker,
+        })
+    })
+}
+
+/// Find the text of a comment in `line` that may contain a marker.
+/// This is either the text after an escape comment symbol, e.g. `//`,
+/// or the text enclosed by a pair of block comment delimiters,
+/// e.g. `/*` and `*/`, which must span the whole line.
+/// The comment symbols are specified in `syntax`.
+/// Returns `None` if `line` is no such comment.
+fn marker_body<'a>(line: &'a str, syntax: &CommentSyntax) -> Option<&'a str> {
+    let text = line.trim();
+    // A line comment symbol must be separated from the marker:
+    if let Some(first) = text.split_whitespace().next() {
+        if syntax.line.iter().any(|s| first == s) {
+            return Some(&text[first.len()..]);
+        }
+    }
+    // Block comment delimiters may be attached to the marker:
+    syntax.block.iter().find_map(|b| {
+        text.strip_prefix(b.open.as_str())
+            .and_then(|t| t.strip_suffix(b.close.as_str()))
+            .map(str::trim_end)
+    })
+}
+
+/// The leading whitespace of `line`.
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Remove the comment symbol (as specified in `syntax`) from `line`
+/// and a single space after it. The indentation of `line` is kept.
+/// Returns `None` if `line` is no comment.
+fn uncomment(line: &str, syntax: &CommentSyntax) -> Option<String> {
+    let text = line.trim_start();
+    let indent = indentation(line);
+    let inner = syntax
+        .line
+        .iter()
+        .find_map(|s| text.strip_prefix(s.as_str()))
+        .or_else(|| {
+            let text = text.trim_end();
+            syntax.block.iter().find_map(|b| {
+                text.strip_prefix(b.open.as_str())
+                    .and_then(|t| t.strip_suffix(b.close.as_str()))
+                    .map(|t| t.strip_suffix(' ').unwrap_or(t))
+            })
+        })?;
+    Some(format!(
+        "{}{}",
+        indent,
+        inner.strip_prefix(' ').unwrap_or(inner)
+    ))
+}
+
+/// All open regions in the order of their opening lines. The regions
+/// are the open modes in `mode`, the +IF regions in `conditions`
+/// and the active code snippets in `coll`.
+fn open_regions(
+    mode: &Mode,
+    conditions: &[Condition],
+    coll: &HashMap<String, Record>,
+) -> Vec<Region> {
+    let modes = [
+        (mode.out, "+OUT"),
+        (mode.var, "+VAR"),
+        (mode.exc, "+EXC"),
+        (mode.excsubst, "+EXCSUBST"),
+        (mode.hl, "+HL"),
+        (mode.header, "+HEADER"),
+    ];
+    let mut regions: Vec<Region> = modes
+        .iter()
+        .filter_map(|&(opened, keyword)| {
+            opened.map(|line| Region {
+                line,
+                keyword,
+                marker: keyword.to_string(),
+            })
+        })
+        .collect();
+    regions.extend(conditions.iter().map(|c| Region {
+        line: c.line,
+        keyword: "+IF",
+        marker: "+IF".to_string(),
+    }));
+    regions.extend(
+        coll.iter()
+            .filter(|(label, r)| r.active && *label != DEFAULTLABEL)
+            .map(|(label, r)| Region {
+                line: r.opened,
+                keyword: "+IN",
+                marker: format!("+IN {}", label),
+            }),
+    );
+    regions.sort_by_key(|r| r.line);
+    regions
+}
+
+/// Check that the region opened in line `opened` contains all
+/// `regions` opened after it, i.e. that no regions cross when it is
+/// closed. Code snippets may overlap each other, though.
+fn check_nesting(opened: Option<usize>, regions: &[Region]) -> Result<(), Error> {
+    let Some(opened) = opened else {
+        return Ok(()); // The region is not open.
+    };
+    let snippet = regions
+        .iter()
+        .any(|r| r.line == opened && r.keyword == "+IN");
+    match regions
+        .iter()
+        .find(|r| r.line > opened && !(snippet && r.keyword == "+IN"))
+    {
+        Some(inner) => Err(Error::new(
+            ErrorKind::Crossing,
+            &format!("Crossing regions: {} is still open", inner.marker),
+        )
+        .opened_at(Some(inner.line))),
+        None => Ok(()),
+    }
+}
+
+/// Print `text` (from line `line_no`) in all active code snippets.
+/// The line is highlighted if `highlight` is true.
+fn print(coll: &mut HashMap<String, Record>, text: &str, line_no: usize, highlight: bool) {
+    for r in coll.values_mut() {
+        if r.active {
+            r.push(text, line_no, highlight);
+        }
+    }
+}
+
+/// How the snippets of a line are laid out.
+/// @param defaults attributes of snippets without attributes.
+/// @param indent   indentation of an ellipsis.
+struct Layout<'a> {
+    defaults: &'a Attributes,
+    indent: &'a str,
+}
+
+/// Start the code snippet `label` in line `line_no`. The `attributes`,
+/// if any, replace those of earlier fragments. New snippets without
+/// attributes get the defaults of `layout`.
+fn open_snippet(
+    label: String,
+    line_no: usize,
+    attributes: Option<Attributes>,
+    layout: &Layout,
+    coll: &mut HashMap<String, Record>,
+) {
+    let new = !coll.contains_key(&label);
+    start(label.clone(), line_no, coll);
+    let record = coll.get_mut(&label).unwrap();
+    match attributes {
+        Some(attributes) => record.attributes = attributes,
+        None if new => record.attributes = layout.defaults.clone(),
+        None => (),
+    }
+    if !record.ends_with_ellipsis() && line_no > 1 {
+        // Print ... but not at the beginning of the file
+        // or when ... was printed at the end of a code snippet.
+        record.push_ellipsis(layout.indent);
     }
 }
 
-fn end(label: String, coll: &mut HashMap<String, Record>) -> Result<(), String> {
+/// End the code snippet `label` in line `line_no`. The file has
+/// `no_lines` lines.
+fn close_snippet(
+    label: String,
+    line_no: usize,
+    no_lines: usize,
+    layout: &Layout,
+    coll: &mut HashMap<String, Record>,
+) -> Result<(), Error> {
+    end(label.clone(), coll)?;
+    if line_no < no_lines {
+        // Print ... but not at the end of the file.
+        coll.get_mut(&label).unwrap().push_ellipsis(layout.indent);
+    }
+    Ok(())
+}
+
+fn start(label: String, line_no: usize, coll: &mut HashMap<String, Record>) {
+    match coll.get_mut(&label) {
+        Some(record) => {
+            record.active = true;
+            record.counter += 1;
+            record.fragment = true;
+            record.opened = line_no;
+        }
+        None => {
+            let mut record = Record::new(true);
+            record.opened = line_no;
+            coll.insert(label, record);
+        }
+    }
+}
+
+fn end(label: String, coll: &mut HashMap<String, Record>) -> Result<(), Error> {
     if !coll.contains_key(&label) {
-        Err(format!("End (-) without start (+) for label: {}", label))
+        Err(Error::new(
+            ErrorKind::NotOpen,
+            &format!("End (-) without start (+) for label: {}", label),
+        ))
     } else {
         coll.get_mut(&label).unwrap().active = false;
         Ok(())
@@ -294,36 +1110,327 @@ fn end(label: String, coll: &mut HashMap<String, Record>) -> Result<(), String>
 /// @param active  true if lines are printed.
 /// @param counter number of code snippets (until now)
 /// @param buffer  buffer to collect the output text.
+/// @param ranges  first and last line in the source file of
+///                the lines printed in each code snippet.
+/// @param highlights numbers of the highlighted lines in `buffer`.
+/// @param attributes attributes of the code snippet given with +IN.
+/// @param encoding the encoding of the file the snippet stems from.
 #[derive(PartialEq, Debug)]
 pub struct Record {
     pub active: bool,
     pub counter: i32,
     pub buffer: String,
+    pub ranges: Vec<(usize, usize)>,
+    pub highlights: Vec<usize>,
+    pub attributes: Attributes,
+    pub encoding: TextEncoding,
+    lines: usize,   // number of lines in buffer.
+    fragment: bool, // true if a code snippet started without printed lines.
+    opened: usize,  // line where the current code snippet started.
 }
 
 impl Record {
     fn new(active: bool) -> Self {
         Record {
-            active: active,
+            active,
             counter: 0,
             buffer: String::new(),
+            ranges: Vec::new(),
+            highlights: Vec::new(),
+            attributes: Attributes::default(),
+            encoding: TextEncoding::default(),
+            lines: 0,
+            fragment: true,
+            opened: 0,
+        }
+    }
+
+    /// Print `text` as a line which stems from line `line_no`
+    /// of the source file. The line is highlighted if `highlight` is true.
+    fn push(&mut self, text: &str, line_no: usize, highlight: bool) {
+        self.buffer.push_str(text);
+        self.buffer.push('\n');
+        self.lines += 1;
+        if highlight {
+            self.highlights.push(self.lines);
+        }
+        match self.ranges.last_mut() {
+            Some(range) if !self.fragment => range.1 = line_no,
+            _ => self.ranges.push((line_no, line_no)),
+        }
+        self.fragment = false;
+    }
+
+    /// Print the `banner` lines which stem from no source line.
+    fn push_banner(&mut self, banner: &[String]) {
+        for line in banner {
+            self.buffer.push_str(line);
+            self.buffer.push('\n');
+            self.lines += 1;
+        }
+    }
+
+    /// Insert the `banner` lines before the first line but after a
+    /// shebang line like `#!/bin/sh`.
+    fn prepend_banner(&mut self, banner: &[String]) {
+        let shebang = if self.buffer.starts_with("#!") {
+            self.buffer
+                .find('\n')
+                .map_or(self.buffer.len(), |idx| idx + 1)
+        } else {
+            0
+        };
+        let text: String = banner.iter().map(|line| format!("{}\n", line)).collect();
+        self.buffer.insert_str(shebang, &text);
+        self.lines += banner.len();
+        let first = usize::from(shebang > 0); // The shebang keeps its number.
+        for h in self.highlights.iter_mut().filter(|h| **h > first) {
+            *h += banner.len();
+        }
+    }
+
+    /// Append the lines of `other`, e.g. the next part of a snippet.
+    /// An ellipsis at the beginning of `other` is dropped after an ellipsis.
+    fn append(&mut self, other: &Record) {
+        let mut text = other.buffer.as_str();
+        let mut skipped = 0;
+        if self.ends_with_ellipsis() {
+            if let Some((first, rest)) = text.split_once('\n') {
+                if self.is_ellipsis(first) {
+                    text = rest;
+                    skipped = 1;
+                }
+            }
+        }
+        self.buffer.push_str(text);
+        let offset = self.lines - skipped;
+        self.highlights
+            .extend(other.highlights.iter().map(|h| h + offset));
+        self.lines += other.lines - skipped;
+        self.ranges.extend(&other.ranges);
+        self.counter += other.counter + 1;
+    }
+
+    /// The line which indicates omitted lines or `None` if they are
+    /// not indicated.
+    pub fn ellipsis(&self) -> Option<&str> {
+        match self.attributes.ellipsis.as_deref() {
+            Some("none") => None,
+            Some(ellipsis) => Some(ellipsis),
+            None => Some(ELLIPSIS),
+        }
+    }
+
+    /// Test if `line` is an ellipsis, regardless of its indentation.
+    pub fn is_ellipsis(&self, line: &str) -> bool {
+        self.ellipsis().is_some_and(|e| e == line.trim())
+    }
+
+    /// Test if the last line is an ellipsis.
+    fn ends_with_ellipsis(&self) -> bool {
+        self.buffer
+            .lines()
+            .last()
+            .is_some_and(|l| self.is_ellipsis(l))
+    }
+
+    /// Print an ellipsis line with `indent` to indicate omitted lines.
+    fn push_ellipsis(&mut self, indent: &str) {
+        if let Some(ellipsis) = self.ellipsis().map(str::to_string) {
+            self.buffer.push_str(indent);
+            self.buffer.push_str(&ellipsis);
+            self.buffer.push('\n');
+            self.lines += 1;
         }
     }
+
+    /// Remove the blank lines at the beginning and at the end of
+    /// the snippet and around ellipsis lines.
+    fn trim(&mut self) {
+        let lines: Vec<&str> = self.buffer.lines().collect();
+        let blank = |line: &str| line.trim().is_empty();
+        let mut keep = vec![true; lines.len()];
+        // Blank lines after the beginning or an ellipsis:
+        let mut edge = true;
+        for (idx, line) in lines.iter().enumerate() {
+            if blank(line) {
+                keep[idx] = !edge;
+            } else {
+                edge = self.is_ellipsis(line);
+            }
+        }
+        // Blank lines before the end or an ellipsis:
+        let mut edge = true;
+        for (idx, line) in lines.iter().enumerate().rev() {
+            if blank(line) {
+                keep[idx] = keep[idx] && !edge;
+            } else {
+                edge = self.is_ellipsis(line);
+            }
+        }
+        let mut buffer = String::new();
+        let mut numbers = Vec::new(); // new number of each line.
+        for (line, _) in lines.iter().zip(&keep).filter(|(_, k)| **k) {
+            buffer.push_str(line);
+            buffer.push('\n');
+        }
+        let mut count = 0;
+        for k in &keep {
+            count += usize::from(*k);
+            numbers.push(count);
+        }
+        self.highlights = self
+            .highlights
+            .iter()
+            .filter(|&&h| keep[h - 1])
+            .map(|&h| numbers[h - 1])
+            .collect();
+        self.lines = count;
+        self.buffer = buffer;
+    }
 }
 
+/// Attributes of a code snippet given after the label of +IN, e.g.
+/// `// +IN Slide dedent ellipsis=none lang=java maxlines=20`.
+/// @param dedent   true if the snippet is dedented (`dedent`).
+/// @param trim     true if blank lines at the beginning and the end
+///                 are removed (`trim`).
+/// @param ellipsis line which indicates omitted lines, `none` omits it
+///                 (`ellipsis=...`).
+/// @param lang     suffix of the snippet file (`lang=java`).
+/// @param maxlines maximum number of lines (`maxlines=20`).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Attributes {
+    pub dedent: bool,
+    pub trim: bool,
+    pub ellipsis: Option<String>,
+    pub lang: Option<String>,
+    pub maxlines: Option<usize>,
+}
+
+impl Attributes {
+    /// Read the attributes from their `words`. Returns the invalid
+    /// word and a message if a word is no attribute.
+    fn read(words: &[String]) -> Result<Self, (String, String)> {
+        let mut attributes = Attributes::default();
+        for word in words {
+            let invalid = |message: &str| Err((word.clone(), format!("{}: {}", message, word)));
+            match word.split_once('=') {
+                None if word == "dedent" => attributes.dedent = true,
+                None if word == "trim" => attributes.trim = true,
+                Some(("ellipsis", value)) if !value.is_empty() => {
+                    attributes.ellipsis = Some(value.to_string())
+                }
+                Some(("lang", value)) if !value.is_empty() => {
+                    attributes.lang = Some(value.trim_start_matches('.').to_string())
+                }
+                Some(("maxlines", value)) => match value.parse() {
+                    Ok(n) => attributes.maxlines = Some(n),
+                    Err(_) => return invalid("Expected a number of lines"),
+                },
+                _ => return invalid("Unknown attribute"),
+            }
+        }
+        Ok(attributes)
+    }
+}
+
+/// A +IF region.
+/// @param symbol      symbol after the +IF, e.g. `!java17`.
+/// @param active      true if lines of the current branch are printed.
+/// @param alternative line of the +ELSE if the +ELSE branch was reached.
+/// @param line        line of the +IF.
+struct Condition {
+    symbol: String,
+    active: bool,
+    alternative: Option<usize>,
+    line: usize,
+}
+
+/// An open region.
+/// @param line    line where the region was opened.
+/// @param keyword marker which opened the region, e.g. `+IN`.
+/// @param marker  keyword with its label, e.g. `+IN Slide`.
+struct Region {
+    line: usize,
+    keyword: &'static str,
+    marker: String,
+}
+
+/// The open regions. Each is the line where the region was opened.
 struct Mode {
-    out: bool,
-    var: bool,
-    exc: bool,
-    excsubst: bool,
+    out: Option<usize>,
+    var: Option<usize>,
+    exc: Option<usize>,
+    excsubst: Option<usize>,
+    hl: Option<usize>,
+    header: Option<usize>,
 }
 
 /// `label`: name of the token, `start`: start or end?,
-/// `s`: next line is replaced with this text
+/// `s`: next line is replaced with this text or, if `None`,
+/// with the lines up to +SOLUTION or +ORIGINAL,
+/// `tag`: tag of an exercise to select its solution,
+/// `symbol`: symbol of a condition, negated with a leading !
+#[allow(clippy::enum_variant_names)]
 enum Token {
-    RegularToken { label: String, start: bool },     // +/-IN
-    QuietToken { start: bool },                      // +/-OUT
-    ExerciseToken { start: bool },                   // +/-EXC
-    ReplaceToken { s: String, start: bool },         // +/-VAR
-    ExerciseReplaceToken { s: String, start: bool }, // +/-EXCSUBST
+    // +/-IN with the words of the attributes after the label
+    RegularToken {
+        label: String,
+        start: bool,
+        attributes: Vec<String>,
+    },
+    // +/-OUT
+    QuietToken {
+        start: bool,
+    },
+    // +/-EXC
+    ExerciseToken {
+        start: bool,
+        tag: Option<String>,
+    },
+    // +/-VAR
+    ReplaceToken {
+        s: Option<String>,
+        start: bool,
+    },
+    // +/-EXCSUBST
+    ExerciseReplaceToken {
+        s: Option<String>,
+        start: bool,
+        tag: Option<String>,
+    },
+    // +SOLUTION between the stub and the solution of a +EXCSUBST
+    SolutionToken,
+    // +ORIGINAL between the replacement and the replaced lines of a +VAR
+    OriginalToken,
+    // +/-IF
+    ConditionalToken {
+        symbol: String,
+        start: bool,
+    },
+    // +ELSE
+    AlternativeToken,
+    // +/-HL
+    HighlightToken {
+        start: bool,
+    },
+    // +/-HEADER
+    HeaderToken {
+        start: bool,
+    },
+    // @IN, @OUT, @EXC or @HL in a trailing comment of `text`
+    InlineToken {
+        text: String,
+        marker: Inline,
+    },
+}
+
+/// Markers in a trailing comment which apply to their line only.
+#[derive(Debug)]
+enum Inline {
+    In(String),          // line is a code snippet with this label.
+    Out,                 // line is omitted.
+    Exc(Option<String>), // line is a solution with an optional tag.
+    Hl,                  // line is highlighted.
 }
@@ -1,44 +1,29 @@
 // Unit tests for parser
 
 #![cfg(test)]
-use crate::parser::parse;
+use crate::config::ParseOptions;
+use crate::error::ErrorKind;
+use crate::lang::{CommentSyntax, EllipsisEntry};
 use crate::parser::DEFAULTLABEL;
-use crate::util::Setting;
+use crate::parser::{parse, parse_lines, parse_str, parse_with_syntax, Rename};
+use crate::util::{BlockComment, Schedule, Variable, Variables};
 use indoc::indoc;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 fn str_to_vec(s: &str) -> Vec<&str> {
-    s.split("\n").collect()
-}
-
-fn config_public() -> Setting {
-    // Path is relative to project root.
-    Setting {
-        src_dir: PathBuf::from("tests/testfiles/src"),
-        snippet_dest_dir: PathBuf::from("tests/testfiles/snippets"),
-        src_dest_dir: PathBuf::from("tests/testfiles/src_dest"),
-        file_suffix: vec![".java".to_string()],
-        comment: vec!["//".to_string()],
-        exercise_solution: false,
-        force_update: true,
-        copy_other_files: false,
-        verbosity: 0,
-    }
+    s.lines().collect()
 }
 
-fn config_solution() -> Setting {
-    // Path is relative to project root.
-    Setting {
-        src_dir: PathBuf::from("tests/testfiles/src"),
-        snippet_dest_dir: PathBuf::from("tests/testfiles/snippets"),
-        src_dest_dir: PathBuf::from("tests/testfiles/src_dest"),
-        file_suffix: vec![".java".to_string()],
-        comment: vec!["//".to_string()],
-        exercise_solution: true,
-        force_update: true,
-        copy_other_files: false,
-        verbosity: 0,
-    }
+fn config_public() -> ParseOptions {
+    ParseOptions::new().comment(["//"]).block_comment(vec![
+        BlockComment::from_str("/* */").unwrap(),
+        BlockComment::from_str("<!-- -->").unwrap(),
+    ])
+}
+
+fn config_solution() -> ParseOptions {
+    config_public().exercise_solution(true)
 }
 
 #[test]
@@ -50,7 +35,7 @@ fn test_slide() {
         // -IN Slide
         line 5
         "};
-    let ok = "...\n  line 3\n...\n";
+    let ok = "// ...\n  line 3\n// ...\n";
     let lines = str_to_vec(s);
     let coll = parse(&lines, &config_public()).unwrap();
     let test = coll.get("Slide").unwrap().buffer.as_str();
@@ -71,9 +56,7 @@ fn excsubst() {
         line 1
         // line hint
         line 5
-
         "};
-    // test produces an extra line, therefore this extra line.
     let lines = str_to_vec(s);
     let coll = parse(&lines, &config_public()).unwrap();
     assert_eq!(coll.len(), 1);
@@ -94,9 +77,7 @@ fn excsubst_solution() {
         line 1
         line solution
         line 5
-
         "};
-    // test produces an extra line, therefore this extra line.
     let lines = str_to_vec(s);
     let coll = parse(&lines, &config_solution()).unwrap();
     assert_eq!(coll.len(), 1);
@@ -115,8 +96,10 @@ fn unbalanced_out_on() {
         "};
     let lines = str_to_vec(s);
     let r = parse(&lines, &config_public());
-    assert_eq!(r, Err("Line 4: Another +OUT".to_string()));
-    // assert_eq!(r, Ok(_);
+    let e = r.unwrap_err();
+    assert_eq!(e.to_string(), "Line 4: Another +OUT");
+    assert_eq!(e.kind, ErrorKind::AlreadyOpen);
+    assert_eq!(e.opening_line, Some(2));
 }
 
 #[test]
@@ -128,7 +111,10 @@ fn unbalanced_out_off() {
         "};
     let lines = str_to_vec(s);
     let r = parse(&lines, &config_public());
-    assert_eq!(r, Err("Line 2: -OUT without preceding +OUT".to_string()));
+    assert_eq!(
+        r.unwrap_err().to_string(),
+        "Line 2: -OUT without preceding +OUT"
+    );
 }
 
 #[test]
@@ -142,7 +128,7 @@ fn unbalanced_exc_on() {
         "};
     let lines = str_to_vec(s);
     let r = parse(&lines, &config_public());
-    assert_eq!(r, Err("Line 4: Another +EXC".to_string()));
+    assert_eq!(r.unwrap_err().to_string(), "Line 4: Another +EXC");
 }
 
 #[test]
@@ -154,8 +140,10 @@ fn unbalanced_exc_off() {
         "};
     let lines = str_to_vec(s);
     let r = parse(&lines, &config_public());
-    assert_eq!(r, Err("Line 2: -EXC without preceding +EXC".to_string()));
-    // assert_eq!(r, Ok(_);
+    assert_eq!(
+        r.unwrap_err().to_string(),
+        "Line 2: -EXC without preceding +EXC"
+    );
 }
 
 #[test]
@@ -169,7 +157,7 @@ fn unbalanced_excsubst_on() {
         "};
     let lines = str_to_vec(s);
     let r = parse(&lines, &config_public());
-    assert_eq!(r, Err("Line 4: Another +EXCSUBST".to_string()));
+    assert_eq!(r.unwrap_err().to_string(), "Line 4: Another +EXCSUBST");
 }
 
 #[test]
@@ -182,11 +170,104 @@ fn unbalanced_excsubst_off() {
     let lines = str_to_vec(s);
     let r = parse(&lines, &config_public());
     assert_eq!(
-        r,
-        Err("Line 2: -EXCSUBST without preceding +EXCSUBST".to_string())
+        r.unwrap_err().to_string(),
+        "Line 2: -EXCSUBST without preceding +EXCSUBST"
     );
 }
 
+#[test]
+fn error_render() {
+    let s = indoc! {"
+        line 1
+        // +EXCSUBST 0 hint
+        line 3
+          // +EXCSUBST 0 hint
+        line 5
+        "};
+    let lines = str_to_vec(s);
+    let e = parse(&lines, &config_public())
+        .unwrap_err()
+        .in_file(&PathBuf::from("src/Foo.java"));
+    assert_eq!(e.to_string(), "src/Foo.java:4: Another +EXCSUBST");
+    assert_eq!(e.column, Some(6));
+    assert_eq!(e.marker.as_deref(), Some("+EXCSUBST"));
+    let expected = indoc! {"
+        error: Another +EXCSUBST
+         --> src/Foo.java:4:6
+          |
+        4 |   // +EXCSUBST 0 hint
+          |      ^^^^^^^^^
+          = note: region was opened in line 2
+        "};
+    assert_eq!(e.render(), expected);
+}
+
+#[test]
+fn unclosed_out() {
+    let s = indoc! {"
+        line 1
+        // +OUT
+        line 3
+        "};
+    let lines = str_to_vec(s);
+    let e = parse(&lines, &config_public()).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Unclosed);
+    assert_eq!(e.to_string(), "Line 2: +OUT is not closed");
+}
+
+#[test]
+fn unclosed_snippet() {
+    let s = indoc! {"
+        // +IF java17
+        line 2
+        // -IF
+          // +IN Slide
+        line 5
+        "};
+    let lines = str_to_vec(s);
+    let e = parse(&lines, &config_public()).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Unclosed);
+    assert_eq!(e.to_string(), "Line 4: +IN Slide is not closed");
+    assert_eq!(e.column, Some(6));
+}
+
+#[test]
+fn crossing_regions() {
+    let s = indoc! {"
+        // +IN Slide
+        line 2
+        // +EXC
+        line 4
+        // -IN Slide
+        // -EXC
+        "};
+    let lines = str_to_vec(s);
+    let e = parse(&lines, &config_public()).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Crossing);
+    assert_eq!(
+        e.to_string(),
+        "Line 5: Crossing regions: +EXC is still open"
+    );
+    assert_eq!(e.opening_line, Some(3));
+}
+
+#[test]
+fn overlapping_snippets() {
+    let s = indoc! {"
+        // +IN A
+        line 2
+        // +IN B
+        line 4
+        // -IN A
+        line 6
+        // -IN B
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    assert_eq!(coll.get("A").unwrap().buffer, "line 2\nline 4\n// ...\n");
+    assert_eq!(coll.get("B").unwrap().buffer, "// ...\nline 4\nline 6\n");
+}
+
 #[test]
 fn indented_label() {
     let s = indoc! {"
@@ -200,9 +281,7 @@ fn indented_label() {
             line 1
             // line hint
             line 5
-    
             "};
-    // test produces an extra line, therefore this extra line.
     let lines = str_to_vec(s);
     let coll = parse(&lines, &config_public()).unwrap();
     assert_eq!(coll.len(), 1);
@@ -225,12 +304,872 @@ fn nested_out_in() {
             "};
     let ok = indoc! {"
             line 1
-    
             "};
-    // test produces an extra line, therefore this extra line.
     let lines = str_to_vec(s);
     let coll = parse(&lines, &config_public()).unwrap();
     assert_eq!(coll.len(), 2);
     let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
     assert_eq!(test, ok);
 }
+
+#[test]
+fn block_comment_slide() {
+    let s = indoc! {"
+        line 1
+        /* +IN Slide */
+          line 3
+        /*-IN Slide*/
+        line 5
+        "};
+    let ok = "// ...\n  line 3\n// ...\n";
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    let test = coll.get("Slide").unwrap().buffer.as_str();
+    assert_eq!(coll.len(), 2);
+    assert_eq!(test, ok);
+}
+
+#[test]
+fn block_comment_excsubst() {
+    let s = indoc! {"
+        line 1
+          <!-- +EXCSUBST 0 line hint -->
+        line solution
+        <!-- -EXCSUBST -->
+        line 5
+        <!-- +OUT --> not a marker
+        "};
+    let ok = indoc! {"
+        line 1
+        line hint
+        line 5
+        <!-- +OUT --> not a marker
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    assert_eq!(coll.len(), 1);
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, ok);
+}
+
+#[test]
+fn lang_syntax() {
+    let s = indoc! {"
+        line 1
+        # +OUT
+        line 3
+        # -OUT
+        // +OUT
+        <!-- +EXC -->
+        "};
+    let ok = indoc! {"
+        line 1
+        // +OUT
+        <!-- +EXC -->
+        "};
+    let lines = str_to_vec(s);
+    let syntax = CommentSyntax::from_str("#").unwrap();
+    let coll = parse_with_syntax(&lines, &syntax, &config_public()).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, ok);
+}
+
+#[test]
+fn tagged_exc_reveal() {
+    let s = indoc! {"
+        line 1
+        // +EXC week1
+        solution 1
+        // -EXC week1
+        // +EXC:week2
+        solution 2
+        // -EXC
+        // +EXCSUBST:week2 0 hint 2
+        solution 2
+        // -EXCSUBST
+        // +EXC
+        solution
+        // -EXC
+        "};
+    let ok = indoc! {"
+        line 1
+        solution 1
+        hint 2
+        "};
+    let lines = str_to_vec(s);
+    let mut options = config_public();
+    options.reveal = vec!["week1".to_string()];
+    let coll = parse(&lines, &options).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, ok);
+}
+
+#[test]
+fn tagged_exc_schedule() {
+    let s = indoc! {"
+        // +EXC week1
+        solution 1
+        // -EXC
+        // +EXCSUBST:week2 0 hint 2
+        solution 2
+        // -EXCSUBST
+        "};
+    let ok = indoc! {"
+        solution 1
+        hint 2
+        "};
+    let lines = str_to_vec(s);
+    let mut options = config_public();
+    options.reveal_schedule = Some(Schedule::read("tests/testfiles/schedule.txt").unwrap());
+    let coll = parse(&lines, &options).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, ok);
+}
+
+#[test]
+fn conditional() {
+    let s = indoc! {"
+        line 1
+        // +IF java17
+        record Foo() {}
+        // +ELSE
+        class Foo {}
+        // -IF
+        // +IF !java17
+        // +IF kotlin
+        data class Foo()
+        // -IF kotlin
+        line 11
+        // -IF
+        "};
+    let ok_java17 = indoc! {"
+        line 1
+        record Foo() {}
+        "};
+    let ok_other = indoc! {"
+        line 1
+        class Foo {}
+        line 11
+        "};
+    let lines = str_to_vec(s);
+    let mut options = config_public();
+    options.define = vec!["java17".to_string()];
+    let coll = parse(&lines, &options).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, ok_java17);
+    let coll = parse(&lines, &config_public()).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, ok_other);
+}
+
+#[test]
+fn crossing_conditions() {
+    let s = indoc! {"
+        // +IF a
+        // +IF b
+        line 3
+        // -IF a
+        // -IF b
+        "};
+    let lines = str_to_vec(s);
+    let e = parse(&lines, &config_public()).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::Crossing);
+    assert_eq!(
+        e.to_string(),
+        "Line 4: Crossing regions: +IF b is still open"
+    );
+    assert_eq!(e.opening_line, Some(2));
+}
+
+#[test]
+fn unbalanced_else() {
+    let s = indoc! {"
+        // +IF java17
+        line 2
+        // +ELSE
+        line 4
+        // +ELSE
+        "};
+    let lines = str_to_vec(s);
+    let r = parse(&lines, &config_public());
+    assert_eq!(r.unwrap_err().to_string(), "Line 5: Another +ELSE");
+}
+
+#[test]
+fn excsubst_block() {
+    let s = indoc! {"
+        int foo() {
+          // +EXCSUBST
+          // // TODO: compute the result
+          // return 0;
+          // +SOLUTION
+          int r = 6 * 7;
+          // Return the result:
+          return r;
+          // -EXCSUBST
+        }
+        "};
+    let ok_public = indoc! {"
+        int foo() {
+          // TODO: compute the result
+          return 0;
+        }
+        "};
+    let ok_solution = indoc! {"
+        int foo() {
+          int r = 6 * 7;
+          // Return the result:
+          return r;
+        }
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, ok_public);
+    let coll = parse(&lines, &config_solution()).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, ok_solution);
+}
+
+/// A solution may start with a comment, the stub ends with +SOLUTION.
+#[test]
+fn excsubst_block_comment() {
+    let s = indoc! {"
+        // +EXCSUBST
+        // return 0;
+        // +SOLUTION
+        // compute
+        return r;
+        // -EXCSUBST
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, "return 0;\n");
+    let coll = parse(&lines, &config_solution()).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, "// compute\nreturn r;\n");
+}
+
+#[test]
+fn excsubst_block_without_solution() {
+    let s = indoc! {"
+        // +EXCSUBST
+        // return 0;
+        // -EXCSUBST
+        // +SOLUTION
+        "};
+    let lines = str_to_vec(s);
+    let r = parse(&lines, &config_public());
+    assert_eq!(
+        r.unwrap_err().to_string(),
+        "Line 3: -EXCSUBST without preceding +SOLUTION"
+    );
+    let r = parse(&lines[3..].to_vec(), &config_public());
+    assert_eq!(
+        r.unwrap_err().to_string(),
+        "Line 1: +SOLUTION without preceding +EXCSUBST"
+    );
+}
+
+#[test]
+fn var_block() {
+    let s = indoc! {"
+        /* +VAR */
+        /* String url = */
+        /*     \"https://example.com\"; */
+        /* +ORIGINAL */
+        String url = \"http://localhost\";
+        /* -VAR */
+        "};
+    let ok = indoc! {"
+        String url =
+            \"https://example.com\";
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_solution()).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, ok);
+}
+
+/// The replaced lines may start with a comment, the replacement ends
+/// with +ORIGINAL.
+#[test]
+fn var_block_comment() {
+    let s = indoc! {"
+        // +VAR
+        // int v = 17;
+        // +ORIGINAL
+        // TODO remove
+        int v = 21;
+        // -VAR
+        "};
+    let lines = str_to_vec(s);
+    for options in [config_public(), config_solution()] {
+        let coll = parse(&lines, &options).unwrap();
+        let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+        assert_eq!(test, "int v = 17;\n");
+    }
+    let r = parse(&lines[..2].to_vec(), &config_public());
+    assert_eq!(r.unwrap_err().to_string(), "Line 1: +VAR is not closed");
+    let r = parse(
+        &vec!["// +VAR", "// int v = 17;", "// -VAR"],
+        &config_public(),
+    );
+    assert_eq!(
+        r.unwrap_err().to_string(),
+        "Line 3: -VAR without preceding +ORIGINAL"
+    );
+}
+
+#[test]
+fn excsubst_indent() {
+    let s = indoc! {"
+        {
+            // +EXCSUBST return null;
+            return 42;
+            // -EXCSUBST
+          // +EXCSUBST 2 return 0;
+          return 1;
+          // -EXCSUBST
+        }
+        "};
+    let ok = indoc! {"
+        {
+            return null;
+          return 0;
+        }
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert_eq!(test, ok);
+}
+
+#[test]
+fn line_ranges() {
+    let s = indoc! {"
+        line 1
+        // +IN Slide
+        line 3
+        // +OUT
+        line 5
+        // -OUT
+        line 7
+        // -IN Slide
+        line 9
+        // +IN Slide
+        line 11
+        // -IN Slide
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    assert_eq!(coll.get("Slide").unwrap().ranges, vec![(3, 7), (11, 11)]);
+    assert_eq!(coll.get(DEFAULTLABEL).unwrap().ranges, vec![(1, 11)]);
+}
+
+#[test]
+fn highlight() {
+    let s = indoc! {"
+        line 1
+        // +IN Slide
+        line 3
+        // +HL
+        line 5
+        line 6
+        // -HL
+        line 8
+        // -IN Slide
+        // +HL
+        line 11
+        // -HL
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    let test = coll.get("Slide").unwrap();
+    assert_eq!(
+        test.buffer,
+        "// ...\nline 3\nline 5\nline 6\nline 8\n// ...\n"
+    );
+    assert_eq!(test.highlights, vec![3, 4]);
+    assert_eq!(coll.get(DEFAULTLABEL).unwrap().highlights, vec![3, 4, 6]);
+}
+
+#[test]
+fn unbalanced_hl_off() {
+    let s = indoc! {"
+        line 1
+        // -HL
+        "};
+    let lines = str_to_vec(s);
+    let r = parse(&lines, &config_public());
+    assert_eq!(
+        r.unwrap_err().to_string(),
+        "Line 2: -HL without preceding +HL"
+    );
+}
+
+#[test]
+fn inline_markers() {
+    let s = indoc! {"
+        String url = \"http://x\"; // @IN Slide
+        int a = 1; // not a marker
+        a++; // @OUT
+          return a; // @EXC week1
+        a--; /* @HL */
+        // @OUT
+        "};
+    let ok_public = indoc! {"
+        String url = \"http://x\";
+        int a = 1; // not a marker
+        a--;
+        // @OUT
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap();
+    assert_eq!(test.buffer, ok_public);
+    assert_eq!(test.highlights, vec![3]);
+    let test = coll.get("Slide").unwrap().buffer.as_str();
+    assert_eq!(test, "String url = \"http://x\";\n// ...\n");
+    let coll = parse(&lines, &config_solution()).unwrap();
+    let test = coll.get(DEFAULTLABEL).unwrap().buffer.as_str();
+    assert!(test.contains("\n  return a;\n"));
+}
+
+/// An inline snippet is only opened for a printed line.
+#[test]
+fn inline_snippet_not_printed() {
+    let s = indoc! {"
+        a;
+        // +OUT
+        b; // @IN S
+        // -OUT
+        c;
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    assert!(!coll.contains_key("S"));
+    let s = indoc! {"
+        // +IN S
+        a;
+        c; // @IN S
+        // -IN S
+        "};
+    let lines = str_to_vec(s);
+    let e = parse(&lines, &config_public()).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::AlreadyOpen);
+    assert_eq!(e.to_string(), "Line 3: Snippet S is already open");
+    assert_eq!(e.opening_line, Some(1));
+}
+
+#[test]
+fn unknown_directives() {
+    let s = indoc! {"
+        // +IN Slide
+        // +EXCSUSBT 4 return 0;
+        int a = 1;
+        // +in Slide
+        // -1 is no directive
+        // +OUT now
+        // -IN Slide
+        "};
+    let lines = str_to_vec(s);
+    let syntax = config_public().default_syntax();
+    let mut warnings = Vec::new();
+    let coll = parse_lines(&lines, &syntax, &config_public(), &mut warnings).unwrap();
+    // The lines are still printed:
+    assert_eq!(
+        coll.get("Slide").unwrap().buffer,
+        "// +EXCSUSBT 4 return 0;\nint a = 1;\n// +in Slide\n// -1 is no directive\n// +OUT now\n"
+    );
+    let warnings: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+    assert_eq!(
+        warnings,
+        vec![
+            "Line 2: Unknown directive +EXCSUSBT, did you mean +EXCSUBST?",
+            "Line 4: Unknown directive +in, did you mean +IN?",
+            "Line 6: Malformed directive +OUT now",
+        ]
+    );
+}
+
+/// Markers escaped by `comment` are reported if the language of the
+/// file has other comment symbols.
+#[test]
+fn ignored_markers() {
+    let s = indoc! {"
+        echo 1
+        // +EXC
+        SECRET SOLUTION
+        // -EXC
+        # +OUT
+        echo 2
+        # -OUT
+        "};
+    let lines = str_to_vec(s);
+    let syntax = config_public().comment_syntax(&PathBuf::from("run.sh"));
+    let mut warnings = Vec::new();
+    let coll = parse_lines(&lines, &syntax, &config_public(), &mut warnings).unwrap();
+    assert_eq!(
+        coll.get(DEFAULTLABEL).unwrap().buffer,
+        "echo 1\n// +EXC\nSECRET SOLUTION\n// -EXC\n"
+    );
+    let warnings: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+    assert_eq!(
+        warnings,
+        vec![
+            "Line 2: Marker +EXC is ignored, its comment symbol is not used for this language",
+            "Line 4: Marker -EXC is ignored, its comment symbol is not used for this language",
+        ]
+    );
+    let options = ParseOptions {
+        strict: true,
+        ..config_public()
+    };
+    let e = parse_lines(&lines, &syntax, &options, &mut Vec::new()).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::IgnoredMarker);
+    assert_eq!(e.column, Some(4));
+}
+
+#[test]
+fn unknown_directive_strict() {
+    let s = indoc! {"
+        line 1
+        // -EXCSUBT
+        "};
+    let lines = str_to_vec(s);
+    let options = ParseOptions {
+        strict: true,
+        ..config_public()
+    };
+    let e = parse(&lines, &options).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::UnknownDirective);
+    assert_eq!(
+        e.to_string(),
+        "Line 2: Unknown directive -EXCSUBT, did you mean -EXCSUBST?"
+    );
+    assert_eq!(e.column, Some(4));
+}
+
+#[test]
+fn marker_prefix() {
+    let s = indoc! {"
+        // +IN Slide
+        line 2
+        // snips:+IN Slide
+        line 4
+        // snips:+EXC:week1
+        line 6
+        // snips:-EXC
+        line 8 // snips:@OUT
+        // snips:-IN Slide
+        line 10
+        "};
+    let lines = str_to_vec(s);
+    let options = ParseOptions {
+        marker_prefix: "snips:".to_string(),
+        ..config_public()
+    };
+    let coll = parse(&lines, &options).unwrap();
+    let test = coll.get("Slide").unwrap();
+    assert_eq!(test.buffer, "// ...\nline 4\n// ...\n");
+}
+
+#[test]
+fn renamed_keywords() {
+    let s = indoc! {"
+        // +IN Slide
+        line 2
+        // +EXC
+        line 4
+        // @@EXC
+        line 6
+        // @@/EXC
+        // -IN Slide
+        // +EXCSUBT
+        "};
+    let lines = str_to_vec(s);
+    let options = ParseOptions {
+        keyword: vec![
+            Rename::from_str("+EXC=@@EXC").unwrap(),
+            Rename::from_str("-EXC=@@/EXC").unwrap(),
+        ],
+        ..config_public()
+    };
+    let syntax = options.default_syntax();
+    let mut warnings = Vec::new();
+    let coll = parse_lines(&lines, &syntax, &options, &mut warnings).unwrap();
+    let test = coll.get("Slide").unwrap();
+    assert_eq!(test.buffer, "line 2\n// +EXC\nline 4\n// ...\n");
+    let warnings: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+    assert_eq!(
+        warnings,
+        vec![
+            "Line 3: Unknown directive +EXC, did you mean @@EXC?",
+            "Line 9: Unknown directive +EXCSUBT, did you mean +EXCSUBST?",
+        ]
+    );
+    assert!(Rename::from_str("+FOO=bar").is_err());
+}
+
+#[test]
+fn reopened_snippet() {
+    let s = indoc! {"
+        line 1
+        // +IN Slide
+        line 3
+        // -IN Slide
+        line 5
+        // +IN Slide
+        line 7
+        // -IN Slide
+        line 9
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    let test = coll.get("Slide").unwrap();
+    assert_eq!(test.buffer, "// ...\nline 3\n// ...\nline 7\n// ...\n");
+}
+
+#[test]
+fn snippet_parts() {
+    let s = indoc! {"
+        // +IN Slide#2
+        line 2
+        // -IN Slide#2
+        line 4
+        // +IN Slide#1
+        // +HL
+        line 7
+        // -HL
+        // -IN Slide#1
+        line 10
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    assert_eq!(
+        coll.get("Slide#1").unwrap().buffer,
+        "// ...\nline 7\n// ...\n"
+    );
+    assert_eq!(coll.get("Slide#2").unwrap().buffer, "line 2\n// ...\n");
+    // The parts are combined in the order of their numbers:
+    let test = coll.get("Slide").unwrap();
+    assert_eq!(test.buffer, "// ...\nline 7\n// ...\nline 2\n// ...\n");
+    assert_eq!(test.ranges, vec![(7, 7), (2, 2)]);
+    assert_eq!(test.highlights, vec![2]);
+}
+
+#[test]
+fn variables() {
+    let s = indoc! {"
+        package ${package};
+        // +IN Slide
+        // ${course} (${variant})
+        echo ${HOME} ${course
+        // -IN Slide
+        "};
+    let lines = str_to_vec(s);
+    let options = ParseOptions {
+        var: vec![Variable::from_str("course=Programming 2").unwrap()],
+        var_file: Some(Variables::read("tests/testfiles/variables.txt").unwrap()),
+        ..config_public()
+    };
+    let coll = parse(&lines, &options).unwrap();
+    // Variables passed on the command line win, unknown ones are kept:
+    let test = coll.get("Slide").unwrap();
+    assert_eq!(
+        test.buffer,
+        "// ...\n// Programming 2 (public)\necho ${HOME} ${course\n"
+    );
+    let full = coll.get(DEFAULTLABEL).unwrap();
+    assert!(full.buffer.starts_with("package de.hsma.pr1;\n"));
+    assert!(Variable::from_str("no variable").is_err());
+}
+
+#[test]
+fn header_region() {
+    let s = indoc! {"
+        // +IN Slide
+        // +HEADER
+        // (c) 2019 Old Course
+        // -HEADER
+        line 5
+        // -IN Slide
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    // The header is kept in the whole file but no part of snippets:
+    assert_eq!(coll.get("Slide").unwrap().buffer, "line 5\n");
+    let full = coll.get(DEFAULTLABEL).unwrap();
+    assert_eq!(full.buffer, "// (c) 2019 Old Course\nline 5\n");
+
+    // A banner replaces the header:
+    let options = ParseOptions {
+        banner: Some("(c) ${year} Course".to_string()),
+        var: vec![Variable::from_str("year=2024").unwrap()],
+        ..config_public()
+    };
+    let coll = parse(&lines, &options).unwrap();
+    let full = coll.get(DEFAULTLABEL).unwrap();
+    assert_eq!(full.buffer, "// (c) 2024 Course\nline 5\n");
+}
+
+#[test]
+fn header_banner() {
+    let s = indoc! {"
+        #!/bin/sh
+        # +IN Slide
+        # +HL
+        echo 3
+        # -HL
+        # -IN Slide
+        "};
+    let lines = str_to_vec(s);
+    let options = ParseOptions {
+        comment: vec!["#".to_string()],
+        banner: Some("License: MIT".to_string()),
+        header_snippets: true,
+        ..config_public()
+    };
+    let coll = parse(&lines, &options).unwrap();
+    let full = coll.get(DEFAULTLABEL).unwrap();
+    assert_eq!(full.buffer, "#!/bin/sh\n# License: MIT\necho 3\n");
+    assert_eq!(full.highlights, vec![3]);
+    let test = coll.get("Slide").unwrap();
+    assert_eq!(test.buffer, "# License: MIT\n# ...\necho 3\n");
+    assert_eq!(test.highlights, vec![3]);
+}
+
+#[test]
+fn snippet_attributes() {
+    let s = indoc! {"
+        line 1
+        // +IN Slide ellipsis=[...] trim
+        // +HL
+
+            line 4
+        // -HL
+        // -IN Slide
+        line 8
+        // +IN Slide
+
+        line 10
+        // -IN Slide
+        // +IN Other ellipsis=none lang=txt
+        line 13
+        // -IN Other
+        line 15
+        // +IN Other
+        line 17
+        // -IN Other
+        "};
+    let lines = str_to_vec(s);
+    let coll = parse(&lines, &config_public()).unwrap();
+    let slide = coll.get("Slide").unwrap();
+    assert_eq!(slide.buffer, "[...]\n    line 4\n[...]\nline 10\n[...]\n");
+    assert_eq!(slide.highlights, vec![2]);
+    assert!(slide.attributes.trim);
+    let other = coll.get("Other").unwrap();
+    assert_eq!(other.buffer, "line 13\nline 17\n");
+    assert_eq!(other.attributes.lang.as_deref(), Some("txt"));
+}
+
+#[test]
+fn invalid_attributes() {
+    let s = indoc! {"
+        // +IN Slide maxlines=2 dednet
+        line 2
+        // -IN Slide
+        // +IN Other maxlines=1
+        line 5
+        line 6
+        // -IN Other
+        "};
+    let lines = str_to_vec(s);
+    let syntax = config_public().default_syntax();
+    let mut warnings = Vec::new();
+    let coll = parse_lines(&lines, &syntax, &config_public(), &mut warnings).unwrap();
+    assert_eq!(coll.get("Slide").unwrap().buffer, "line 2\n// ...\n");
+    let warnings: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+    assert_eq!(
+        warnings,
+        vec![
+            "Line 1: Unknown attribute: dednet",
+            "Line 4: Snippet Other has 2 lines, more than maxlines=1",
+        ]
+    );
+    let options = ParseOptions {
+        strict: true,
+        ..config_public()
+    };
+    let e = parse(&lines, &options).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::InvalidAttribute);
+    assert_eq!(e.column, Some(25));
+}
+
+/// The combined parts of a snippet are reported at the first part.
+#[test]
+fn maxlines_parts() {
+    let s = "a\n// +IN Slide#1 maxlines=1\nb\nc\n// -IN Slide#1\nd\n";
+    let mut warnings = Vec::new();
+    let syntax = config_public().default_syntax();
+    let lines = str_to_vec(s);
+    let coll = parse_lines(&lines, &syntax, &config_public(), &mut warnings).unwrap();
+    assert_eq!(coll.get("Slide").unwrap().buffer, "// ...\nb\nc\n// ...\n");
+    let warnings: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+    assert_eq!(
+        warnings,
+        vec!["Line 2: Snippet Slide has 2 lines, more than maxlines=1"]
+    );
+}
+
+#[test]
+fn ellipsis_per_language() {
+    let s = indoc! {"
+        def main():
+            # +IN Slide
+            print(1)
+            # -IN Slide
+            return 0
+        "};
+    let lines = str_to_vec(s);
+    let options = ParseOptions {
+        ellipsis: vec![
+            EllipsisEntry::from_str("\\ldots").unwrap(),
+            EllipsisEntry::from_str("py=# [...]").unwrap(),
+        ],
+        ellipsis_indent: true,
+        ..config_public()
+    };
+    let python = options.comment_syntax(&PathBuf::from("main.py"));
+    let coll = parse_with_syntax(&lines, &python, &options).unwrap();
+    assert_eq!(
+        coll.get("Slide").unwrap().buffer,
+        "    # [...]\n    print(1)\n    # [...]\n"
+    );
+    // Files with other suffixes get the ellipsis without suffix:
+    let shell = options.comment_syntax(&PathBuf::from("main.sh"));
+    let coll = parse_with_syntax(&lines, &shell, &options).unwrap();
+    assert_eq!(
+        coll.get("Slide").unwrap().buffer,
+        "    \\ldots\n    print(1)\n    \\ldots\n"
+    );
+    let options = ParseOptions {
+        ellipsis: vec![EllipsisEntry::from_str("none").unwrap()],
+        ..config_public()
+    };
+    let shell = options.comment_syntax(&PathBuf::from("main.sh"));
+    let coll = parse_with_syntax(&lines, &shell, &options).unwrap();
+    assert_eq!(coll.get("Slide").unwrap().buffer, "    print(1)\n");
+}
+
+#[test]
+fn parse_text() {
+    let s = "line 1\r\n// +IN Slide\r\nline 3\r\n// -IN Slide";
+    let coll = parse_str(s, &config_public()).unwrap();
+    assert_eq!(coll.get(DEFAULTLABEL).unwrap().buffer, "line 1\r\nline 3");
+    assert_eq!(coll.get("Slide").unwrap().buffer, "// ...\r\nline 3\r\n");
+    let coll = parse_str(s, &config_public().normalize(true)).unwrap();
+    assert_eq!(coll.get(DEFAULTLABEL).unwrap().buffer, "line 1\nline 3\n");
+}
@@ -1,7 +1,17 @@
+use crate::config::{Config, ParseOptions};
+use crate::encoding::read_encoding;
+use crate::lang::{EllipsisEntry, SyntaxEntry, SyntaxTable};
+use crate::parser::Rename;
 use clap::Parser;
+use encoding_rs::Encoding;
+use std::collections::HashMap;
+use std::fs;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::SystemTime;
 
-/// The settings for a snippet run.
+/// The command line settings for a snippet run. The library is
+/// configured with the `Config` they are converted into.
 #[derive(Parser)]
 #[command(name = "snips")]
 #[command(version = "0.2.4")]
@@ -10,6 +20,12 @@ use std::path::PathBuf;
 and copy the stripped files. Useful for source code \
 presentation or exercises.", long_about = None)]
 pub struct Setting {
+    /// Command to run: scan extracts the snippets and writes the files,
+    /// check only validates the markers of all files, weave includes
+    /// the snippets into the documents.
+    #[arg(value_enum, default_value_t = Command::Scan)]
+    pub command: Command,
+
     /// Directory with source files.
     #[arg(short = 's', long, value_name = "directory")]
     pub src_dir: PathBuf,
@@ -40,10 +56,104 @@ pub struct Setting {
     #[arg(short = 'c', long, value_name = "comment", default_value = "#")]
     pub comment: Vec<String>,
 
+    /// One or more pairs of block comment delimiters separated by
+    /// a space, e.g. "/* */" or "<!-- -->".
+    #[arg(short = 'b', long, value_name = "open close")]
+    pub block_comment: Vec<BlockComment>,
+
+    /// Comment symbols for files with a suffix, e.g. "py=#" or
+    /// "java=//,/* */". Overrides the built-in language table.
+    #[arg(short = 'l', long, value_name = "suffix=comments")]
+    pub lang: Vec<SyntaxEntry>,
+
+    /// File with comment symbols, one "suffix=comments" per line.
+    #[arg(long, value_name = "file", value_parser = SyntaxTable::read)]
+    pub lang_file: Option<SyntaxTable>,
+
+    /// Prefix of all markers, e.g. "snips:" for "// snips:+IN Slide".
+    #[arg(long, value_name = "prefix", default_value = "")]
+    pub marker_prefix: String,
+
+    /// Spell one or more keywords differently, e.g. "+EXC=@@EXC".
+    #[arg(long, value_name = "keyword=word", value_delimiter = ',')]
+    pub keyword: Vec<Rename>,
+
     /// Include solutions (EXC and EXCSUBST flags).
     #[arg(short = 'e', long)]
     pub exercise_solution: bool,
 
+    /// Include only the solutions of exercises with these tags, e.g. week1,week2.
+    #[arg(short = 'r', long, value_name = "tag", value_delimiter = ',')]
+    pub reveal: Vec<String>,
+
+    /// File with one "tag date" per line. Solutions of exercises with
+    /// this tag are included from the date (YYYY-MM-DD) on.
+    #[arg(long, value_name = "file", value_parser = Schedule::read)]
+    pub reveal_schedule: Option<Schedule>,
+
+    /// Define one or more symbols for +IF conditions, e.g. java17.
+    #[arg(short = 'D', long, value_name = "symbol", value_delimiter = ',')]
+    pub define: Vec<String>,
+
+    /// Define one or more variables, e.g. "course=Programming 1". A
+    /// variable is expanded in the form ${course}.
+    #[arg(long, value_name = "name=value")]
+    pub var: Vec<Variable>,
+
+    /// File with one "name=value" variable per line.
+    #[arg(long, value_name = "file", value_parser = Variables::read)]
+    pub var_file: Option<Variables>,
+
+    /// Remove the common indentation of all snippets.
+    #[arg(long)]
+    pub dedent: bool,
+
+    /// Remove the common indentation of snippets with these labels.
+    #[arg(long, value_name = "label", value_delimiter = ',')]
+    pub dedent_label: Vec<String>,
+
+    /// Indent dedented snippets by this number of spaces.
+    #[arg(long, value_name = "spaces", default_value_t = 0)]
+    pub indent: usize,
+
+    /// Line which indicates omitted lines of a snippet, e.g. "..." or
+    /// "py=# ...". By default it is "..." as a comment of the
+    /// language. "none" omits these lines.
+    #[arg(long, value_name = "[suffix=]text")]
+    pub ellipsis: Vec<EllipsisEntry>,
+
+    /// Indent an ellipsis like the marker of the omitted lines.
+    #[arg(long)]
+    pub ellipsis_indent: bool,
+
+    /// Report warnings, e.g. unknown directives like +EXCSUSBT, as
+    /// errors.
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Write the source line ranges of each snippet into a .meta file.
+    #[arg(short = 'm', long)]
+    pub metadata: bool,
+
+    /// Header comment before each file's fragment of a snippet with a
+    /// project-wide label, e.g. @Observer. {file} is replaced by the
+    /// file's path. An empty text omits the header.
+    #[arg(long, value_name = "text", default_value = "{file}")]
+    pub merge_header: String,
+
+    /// File with a banner, e.g. a license, which is put as a comment
+    /// at the top of every file in src_dest_dir.
+    #[arg(long, value_name = "file", value_parser = read_text)]
+    pub header: Option<String>,
+
+    /// Banner text instead of a --header file.
+    #[arg(long, value_name = "text")]
+    pub header_text: Option<String>,
+
+    /// Put the banner also at the top of every snippet.
+    #[arg(long)]
+    pub header_snippets: bool,
+
     /// Force (re-)generation of all snippets.
     #[arg(short = 'f', long)]
     pub force_update: bool,
@@ -52,7 +162,258 @@ pub struct Setting {
     #[arg(short = 'o', long)]
     pub copy_other_files: bool,
 
+    /// Write all files with Unix line endings and a final newline
+    /// instead of keeping those of the source files.
+    #[arg(long)]
+    pub normalize: bool,
+
+    /// Encoding of source files without a byte order mark, e.g. latin1
+    /// or windows-1252. By default it is UTF-8 if a file is valid
+    /// UTF-8, Windows-1252 if not.
+    #[arg(long, value_name = "encoding", value_parser = read_encoding)]
+    pub encoding: Option<&'static Encoding>,
+
+    /// Markdown or LaTeX document with include directives to weave.
+    #[arg(long, value_name = "file")]
+    pub document: Vec<PathBuf>,
+
     /// Add this flag multiple times to increase message verbosity.
     #[arg(short= 'v', long, action = clap::ArgAction::Count)]
     pub verbosity: u8,
 }
+
+impl From<Setting> for Config {
+    /// The configuration of the library for the command line `setting`.
+    fn from(setting: Setting) -> Self {
+        let options = ParseOptions {
+            comment: setting.comment,
+            block_comment: setting.block_comment,
+            lang: setting.lang,
+            lang_file: setting.lang_file,
+            marker_prefix: setting.marker_prefix,
+            keyword: setting.keyword,
+            exercise_solution: setting.exercise_solution,
+            reveal: setting.reveal,
+            reveal_schedule: setting.reveal_schedule,
+            define: setting.define,
+            var: setting.var,
+            var_file: setting.var_file,
+            ellipsis: setting.ellipsis,
+            ellipsis_indent: setting.ellipsis_indent,
+            strict: setting.strict,
+            // A banner text takes precedence over a banner file:
+            banner: setting.header_text.or(setting.header),
+            header_snippets: setting.header_snippets,
+            normalize: setting.normalize,
+            encoding: setting.encoding,
+        };
+        Config {
+            src_dir: setting.src_dir,
+            snippet_dest_dir: setting.snippet_dest_dir,
+            src_dest_dir: setting.src_dest_dir,
+            file_suffix: setting.file_suffix,
+            dedent: setting.dedent,
+            dedent_label: setting.dedent_label,
+            indent: setting.indent,
+            metadata: setting.metadata,
+            merge_header: setting.merge_header,
+            force_update: setting.force_update,
+            copy_other_files: setting.copy_other_files,
+            document: setting.document,
+            options,
+        }
+    }
+}
+
+/// The command of a snippet run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Command {
+    /// Extract the snippets and write the files.
+    Scan,
+    /// Check the markers of all files without writing any files.
+    Check,
+    /// Include the snippets into the documents passed with `--document`.
+    Weave,
+}
+
+/// A variable for the expansion of `${name}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for Variable {
+    type Err = String;
+
+    /// Read a variable from a string like `"course=Programming 1"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, value)) if is_variable_name(name.trim()) => Ok(Variable {
+                name: name.trim().to_string(),
+                value: value.trim().to_string(),
+            }),
+            _ => Err(format!("Expected name=value but found: {}", s)),
+        }
+    }
+}
+
+/// The variables of a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variables {
+    pub entries: Vec<Variable>,
+}
+
+impl Variables {
+    /// Read a file with one `name=value` variable per line.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn read(filename: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(filename)
+            .map_err(|e| format!("Cannot read variable file {}: {}", filename, e))?;
+        let entries = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Variable::from_str)
+            .collect::<Result<_, _>>()?;
+        Ok(Variables { entries })
+    }
+}
+
+/// Read the text of the file `filename`.
+fn read_text(filename: &str) -> Result<String, String> {
+    fs::read_to_string(filename).map_err(|e| format!("Cannot read file {}: {}", filename, e))
+}
+
+/// Test if `name` is a valid name of a variable, e.g. `course` or `java.version`.
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+/// Expand all variables like `${course}` in `text`. Undefined variables
+/// are kept, e.g. `${HOME}` in a shell script.
+pub fn expand(text: &str, variables: &HashMap<String, String>) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(idx) = rest.find("${") {
+        result.push_str(&rest[..idx]);
+        let after = &rest[idx + 2..];
+        let value = after
+            .split_once('}')
+            .filter(|(name, _)| is_variable_name(name))
+            .and_then(|(name, tail)| Some((variables.get(name)?, tail)));
+        match value {
+            Some((value, tail)) => {
+                result.push_str(value);
+                rest = tail;
+            }
+            None => {
+                result.push_str("${");
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// A pair of delimiters which enclose a block comment, e.g. `/*` and `*/`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockComment {
+    pub open: String,
+    pub close: String,
+}
+
+impl FromStr for BlockComment {
+    type Err = String;
+
+    /// Read the delimiters from a string like `"/* */"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let delimiters: Vec<&str> = s.split_whitespace().collect();
+        if delimiters.len() != 2 {
+            return Err(format!(
+                "Block comment must consist of an opening and a closing delimiter: {}",
+                s
+            ));
+        }
+        Ok(BlockComment {
+            open: delimiters[0].to_string(),
+            close: delimiters[1].to_string(),
+        })
+    }
+}
+
+/// A schedule that assigns each exercise tag a date from which
+/// on the solutions are published.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    /// Tag and date (year, month, day).
+    pub entries: Vec<(String, (u32, u32, u32))>,
+}
+
+impl Schedule {
+    /// Read a schedule file with one `tag YYYY-MM-DD` entry per line.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn read(filename: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(filename)
+            .map_err(|e| format!("Cannot read schedule file {}: {}", filename, e))?;
+        let mut entries = Vec::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let date = match tokens[..] {
+                [_, date] => parse_date(date),
+                _ => None,
+            };
+            match date {
+                Some(date) => entries.push((tokens[0].to_string(), date)),
+                None => return Err(format!("Expected tag YYYY-MM-DD but found: {}", line)),
+            }
+        }
+        Ok(Schedule { entries })
+    }
+
+    /// Test if the solutions with `tag` are published on the date `day`.
+    pub fn is_due(&self, tag: &str, day: (u32, u32, u32)) -> bool {
+        self.entries
+            .iter()
+            .any(|(t, date)| t == tag && *date <= day)
+    }
+}
+
+/// Read a date like `2022-12-24` as (year, month, day).
+fn parse_date(s: &str) -> Option<(u32, u32, u32)> {
+    let parts: Vec<u32> = s
+        .split('-')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    match parts[..] {
+        [y, m, d] if (1..=12).contains(&m) && (1..=31).contains(&d) => Some((y, m, d)),
+        _ => None,
+    }
+}
+
+/// The current date (UTC) as (year, month, day).
+pub(crate) fn today() -> (u32, u32, u32) {
+    let secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    // Convert days since 1970-01-01 into a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y as u32, m as u32, d as u32)
+}
@@ -0,0 +1,203 @@
+// Module weave
+
+use crate::config::Config;
+use crate::error::{Error, ErrorKind};
+use crate::file::{snippet_text, LineEndings};
+use crate::parse_logged;
+use crate::parser::Record;
+use crate::DEFAULTLABEL;
+use log::{debug, info};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The directive which includes a snippet into a document.
+const INCLUDE: &str = "snips:include";
+
+/// Weave the snippets into all documents of `config`.
+/// A document includes a snippet with a directive like
+/// `<!-- snips:include src/Foo.java#Slide -->` (Markdown) or
+/// `%snips:include{src/Foo.java}{Slide}` (LaTeX). The code block
+/// after the directive is replaced with the current snippet.
+/// Documents are only written if their content changed.
+pub fn weave(config: &Config) -> Result<(), Error> {
+    // Parsed source files:
+    let mut sources: HashMap<PathBuf, HashMap<String, Record>> = HashMap::new();
+    for doc in &config.document {
+        info!(" {}", doc.display());
+        let text =
+            fs::read_to_string(doc).map_err(|e| Error::io("Document cannot be read", doc, e))?;
+        let woven = weave_text(&text, doc, &mut sources, config).map_err(|e| e.in_file(doc))?;
+        if woven != text {
+            fs::write(doc, woven).map_err(|e| Error::io("Document cannot be written", doc, e))?;
+        } else {
+            debug!(" {} not modified", doc.display());
+        }
+    }
+    Ok(())
+}
+
+/// Weave the snippets into the `text` of the document `doc`. Source
+/// files are parsed once and kept in `sources`.
+fn weave_text(
+    text: &str,
+    doc: &Path,
+    sources: &mut HashMap<PathBuf, HashMap<String, Record>>,
+    config: &Config,
+) -> Result<String, Error> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut result: Vec<String> = Vec::new();
+    let mut idx = 0;
+    while idx < lines.len() {
+        let line = lines[idx];
+        let line_no = idx + 1;
+        result.push(line.to_string());
+        idx += 1;
+        let Some(include) = read_include(line) else {
+            continue;
+        };
+        let error = |kind, message: &str| {
+            Error::new(kind, message)
+                .at(line_no, line)
+                .with_marker(INCLUDE)
+        };
+        let Some(source) = find_source(&include.path, doc, config) else {
+            return Err(error(
+                ErrorKind::Include,
+                &format!("Source file {} not found", include.path),
+            ));
+        };
+        if !sources.contains_key(&source) {
+            let coll = parse_logged(&source, config)?;
+            sources.insert(source.clone(), coll);
+        }
+        let label = include.label.as_deref().unwrap_or(DEFAULTLABEL);
+        let Some(record) = sources[&source].get(label) else {
+            return Err(error(
+                ErrorKind::Include,
+                &format!("Snippet {} not found in {}", label, include.path),
+            ));
+        };
+        // Whole files are never dedented, like in `scan`:
+        let snippet = if include.label.is_none() {
+            record.buffer.clone()
+        } else {
+            snippet_text(label, record, config)
+        };
+
+        // Skip blank lines up to the code block which is replaced:
+        let mut next = idx;
+        while next < lines.len() && lines[next].trim().is_empty() {
+            next += 1;
+        }
+        match lines.get(next).and_then(|l| block_end(l, include.latex)) {
+            Some(end) => {
+                // Keep the lines up to and including the opening line:
+                result.extend(lines[idx..=next].iter().map(|l| l.to_string()));
+                let close = lines[next + 1..]
+                    .iter()
+                    .position(|l| l.trim() == end)
+                    .map(|pos| next + 1 + pos)
+                    .ok_or_else(|| {
+                        Error::new(ErrorKind::Unclosed, "Code block is not closed")
+                            .at(next + 1, lines[next])
+                    })?;
+                result.extend(snippet.lines().map(|l| l.to_string()));
+                result.push(lines[close].to_string());
+                idx = close + 1;
+            }
+            None => {
+                // There is no code block yet, so it is inserted:
+                let (open, close) = if include.latex {
+                    ("\\begin{verbatim}".to_string(), "\\end{verbatim}")
+                } else {
+                    let lang = match &record.attributes.lang {
+                        Some(lang) => lang,
+                        None => source.extension().and_then(|e| e.to_str()).unwrap_or(""),
+                    };
+                    (format!("```{}", lang), "```")
+                };
+                result.push(open);
+                result.extend(snippet.lines().map(|l| l.to_string()));
+                result.push(close.to_string());
+            }
+        }
+    }
+    let endings = LineEndings::detect(text);
+    let mut woven = result.join(endings.eol());
+    if text.ends_with('\n') {
+        woven.push_str(endings.eol());
+    }
+    Ok(woven)
+}
+
+/// An include directive.
+/// @param path  path of the source file.
+/// @param label label of the snippet, `None` for the whole file.
+/// @param latex true for the LaTeX form of the directive.
+struct Include {
+    path: String,
+    label: Option<String>,
+    latex: bool,
+}
+
+/// Read an include directive in `line`, either
+/// `<!-- snips:include src/Foo.java#Slide -->` or
+/// `%snips:include{src/Foo.java}{Slide}`. The label is optional.
+fn read_include(line: &str) -> Option<Include> {
+    let text = line.trim();
+    if let Some(body) = text
+        .strip_prefix("<!--")
+        .and_then(|t| t.strip_suffix("-->"))
+        .and_then(|t| t.trim().strip_prefix(INCLUDE))
+    {
+        let target = body.trim();
+        if target.is_empty() || target.contains(char::is_whitespace) {
+            return None;
+        }
+        let (path, label) = match target.split_once('#') {
+            Some((path, label)) => (path, Some(label.to_string())),
+            None => (target, None),
+        };
+        return Some(Include {
+            path: path.to_string(),
+            label,
+            latex: false,
+        });
+    }
+    let args = text.strip_prefix('%')?.trim_start().strip_prefix(INCLUDE)?;
+    let (path, rest) = args.strip_prefix('{')?.split_once('}')?;
+    let label = match rest.trim() {
+        "" => None,
+        rest => Some(rest.strip_prefix('{')?.strip_suffix('}')?.to_string()),
+    };
+    Some(Include {
+        path: path.trim().to_string(),
+        label,
+        latex: true,
+    })
+}
+
+/// The source file `path` of an include directive in the document `doc`.
+/// `path` is relative to the document's directory or, if not found
+/// there, to the source directory of `config`.
+fn find_source(path: &str, doc: &Path, config: &Config) -> Option<PathBuf> {
+    let dir = doc.parent().unwrap_or(Path::new(""));
+    [dir.join(path), config.src_dir.join(path)]
+        .into_iter()
+        .find(|p| p.is_file())
+}
+
+/// The closing line of the code block opened by `line`, e.g. a
+/// Markdown fence like ```` ```java ```` or `\begin{lstlisting}`
+/// if `latex` is true. Returns `None` if `line` opens no code block.
+fn block_end(line: &str, latex: bool) -> Option<String> {
+    let text = line.trim();
+    if latex {
+        let env = text.strip_prefix("\\begin{")?.split_once('}')?.0;
+        Some(format!("\\end{{{}}}", env))
+    } else {
+        let fence = ["```", "~~~"].into_iter().find(|f| text.starts_with(f))?;
+        Some(fence.to_string())
+    }
+}
@@ -3,10 +3,15 @@
 mod tests {
 
     use file_diff::diff_files;
-    use snips::scan;
-    use snips::util::Setting;
+    use snips::config::{Config, ParseOptions};
+    use snips::encoding::read_encoding;
+    use snips::error::ErrorKind;
+    use snips::weave::weave;
+    use snips::{check, scan};
+    use std::fs;
     use std::fs::File;
     use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
 
     // Here a the correct files:
     fn src_templ() -> &'static Path {
@@ -27,13 +32,90 @@ mod tests {
                 gen.join("Testfile_IN_Slide_Slide.java"),
                 corr.join("Testfile_IN_Slide_Slide-public.java"),
             ),
+            (
+                gen.join("Testfile_IN_Slide_Slide.java.meta"),
+                corr.join("Testfile_IN_Slide_Slide-public.java.meta"),
+            ),
+            (
+                gen.join("Testfile_HL_Slide.java.meta"),
+                corr.join("Testfile_HL_Slide-public.java.meta"),
+            ),
+            (
+                gen.join("Testfile_IN_Lang_Slide.py"),
+                corr.join("Testfile_IN_Lang_Slide-public.py"),
+            ),
+            (
+                gen.join("@Observer.java"),
+                corr.join("@Observer-public.java"),
+            ),
+            (
+                gen.join("@Observer.java.meta"),
+                corr.join("@Observer-public.java.meta"),
+            ),
+            (
+                gen.join("Testfile_EXCSUBST.java"),
+                corr.join("Testfile_EXCSUBST-public.java"),
+            ),
+            (
+                gen.join("Testfile_EXCSUBST_OUT_Nested.java"),
+                corr.join("Testfile_EXCSUBST_OUT_Nested-public.java"),
+            ),
+            (
+                gen.join("Testfile_OUT_EXC.java"),
+                corr.join("Testfile_OUT_EXC-public.java"),
+            ),
+            (
+                gen.join("Testfile_OUT_EXC_Nested.java"),
+                corr.join("Testfile_OUT_EXC_Nested-public.java"),
+            ),
+        ]
+    }
+
+    // All file pairs for generated snippet files with solutions:
+    fn solution_files() -> Vec<(PathBuf, PathBuf)> {
+        let gen = solution_config().snippet_dest_dir;
+        let corr = src_templ();
+        // generated file; correct file
+        vec![
+            (
+                gen.join("Testfile_IN_Slide.java"),
+                corr.join("Testfile_IN_Slide-solution.java"),
+            ),
+            (
+                gen.join("Testfile_IN_Slide_Slide.java"),
+                corr.join("Testfile_IN_Slide_Slide-solution.java"),
+            ),
+            (
+                gen.join("Testfile_IN_Lang_Slide.py"),
+                corr.join("Testfile_IN_Lang_Slide-solution.py"),
+            ),
+            (
+                gen.join("@Observer.java"),
+                corr.join("@Observer-solution.java"),
+            ),
+            (
+                gen.join("Testfile_EXCSUBST.java"),
+                corr.join("Testfile_EXCSUBST-solution.java"),
+            ),
+            (
+                gen.join("Testfile_EXCSUBST_OUT_Nested.java"),
+                corr.join("Testfile_EXCSUBST_OUT_Nested-solution.java"),
+            ),
+            (
+                gen.join("Testfile_OUT_EXC.java"),
+                corr.join("Testfile_OUT_EXC-solution.java"),
+            ),
+            (
+                gen.join("Testfile_OUT_EXC_Nested.java"),
+                corr.join("Testfile_OUT_EXC_Nested-solution.java"),
+            ),
         ]
     }
 
     // Compare all files for equality.
     // Important: even the newline representation (Windows vs. Unix)
     // must be equal.
-    fn check_files(file_pairs: &Vec<(PathBuf, PathBuf)>, _s: &Setting) -> bool {
+    fn check_files(file_pairs: &Vec<(PathBuf, PathBuf)>, _s: &Config) -> bool {
         for (filepath1, filepath2) in file_pairs {
             let mut file1 = File::open(filepath1).expect("f1");
             let mut file2 = File::open(filepath2).expect("f1");
@@ -46,34 +128,25 @@ mod tests {
         true
     }
 
-    fn public_config() -> Setting {
+    fn public_config() -> Config {
         // Path is relative to project root.
-        Setting {
-            src_dir: PathBuf::from("tests/testfiles/src"),
-            snippet_dest_dir: PathBuf::from("tests/testfiles/public/snippets"),
-            src_dest_dir: PathBuf::from("tests/testfiles/public/src_dest"),
-            file_suffix: vec![".java".to_string()],
-            comment: vec!["//".to_string()],
-            exercise_solution: false,
-            force_update: true,
-            copy_other_files: false,
-            verbosity: 0,
-        }
+        Config::new("tests/testfiles/src")
+            .snippet_dest_dir("tests/testfiles/public/snippets")
+            .src_dest_dir("tests/testfiles/public/src_dest")
+            .file_suffix([".java", ".py"])
+            .metadata(true)
+            .force_update(true)
+            .options(ParseOptions::new().comment(["//"]))
     }
 
-    fn solution_config() -> Setting {
+    fn solution_config() -> Config {
         // Path is relative to project root.
-        Setting {
-            src_dir: PathBuf::from("tests/testfiles/src"),
-            snippet_dest_dir: PathBuf::from("tests/testfiles/solution/snippets"),
-            src_dest_dir: PathBuf::from("tests/testfiles/solution/src_dest"),
-            file_suffix: vec![".java".to_string()],
-            comment: vec!["//".to_string()],
-            exercise_solution: true,
-            force_update: true,
-            copy_other_files: false,
-            verbosity: 0,
-        }
+        Config::new("tests/testfiles/src")
+            .snippet_dest_dir("tests/testfiles/solution/snippets")
+            .src_dest_dir("tests/testfiles/solution/src_dest")
+            .file_suffix([".java", ".py"])
+            .force_update(true)
+            .options(ParseOptions::new().comment(["//"]).exercise_solution(true))
     }
 
     /// Run a full test.
@@ -82,15 +155,292 @@ mod tests {
         let s = &public_config();
         let r = scan(s);
         assert_eq!(r, Ok(()));
-        assert_eq!(check_files(&public_files(), s), true);
+        assert!(check_files(&public_files(), s));
     }
 
     /// Run a full test.
     #[test]
     fn scan_new_dirs_solution() {
-        // TODO
-        let _setting = solution_config();
-        // scan(setting);
-        assert_eq!(true, true);
+        let s = &solution_config();
+        let r = scan(s);
+        assert_eq!(r, Ok(()));
+        assert!(check_files(&solution_files(), s));
+    }
+
+    /// Snippets with label Slide are dedented and indented by two spaces.
+    #[test]
+    fn scan_dedent() {
+        let s = &public_config()
+            .snippet_dest_dir("tests/testfiles/dedent/snippets")
+            .src_dest_dir("tests/testfiles/dedent/src_dest")
+            .dedent_label(["Slide"])
+            .indent(2);
+        let r = scan(s);
+        assert_eq!(r, Ok(()));
+        let gen = &s.snippet_dest_dir;
+        let corr = src_templ();
+        let files = vec![
+            (
+                gen.join("Testfile_IN_Slide_Slide.java"),
+                corr.join("Testfile_IN_Slide_Slide-dedent.java"),
+            ),
+            (
+                gen.join("Testfile_IN_Lang_Slide.py"),
+                corr.join("Testfile_IN_Lang_Slide-dedent.py"),
+            ),
+        ];
+        assert!(check_files(&files, s));
+    }
+
+    /// Line endings and a missing final newline are kept, unless they
+    /// are normalized.
+    #[test]
+    fn scan_line_endings() {
+        let dir = Path::new("tests/testfiles/crlf");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let text = "line 1\r\n  // +IN Slide\r\n  line 3\r\n  // -IN Slide\r\n}";
+        fs::write(dir.join("src/Crlf.java"), text).unwrap();
+        let merged = "// +IN @Merged\r\ny\r\n// -IN @Merged\r\n";
+        fs::write(dir.join("src/Merged.java"), merged).unwrap();
+        let config = |normalize| {
+            public_config()
+                .src_dir(dir.join("src"))
+                .snippet_dest_dir(dir.join("snippets"))
+                .src_dest_dir(dir.join("src_dest"))
+                .dedent(true)
+                .options(ParseOptions::new().comment(["//"]).normalize(normalize))
+        };
+        assert_eq!(scan(&config(false)), Ok(()));
+        let read = |file: &str| fs::read_to_string(dir.join(file)).unwrap();
+        assert_eq!(read("src_dest/Crlf.java"), "line 1\r\n  line 3\r\n}");
+        assert_eq!(
+            read("snippets/Crlf_Slide.java"),
+            "// ...\r\nline 3\r\n// ...\r\n"
+        );
+        assert_eq!(read("snippets/@Merged.java"), "// Merged.java\r\ny\r\n");
+        assert_eq!(scan(&config(true)), Ok(()));
+        assert_eq!(read("src_dest/Crlf.java"), "line 1\n  line 3\n}\n");
+    }
+
+    /// Files in Latin-1 and UTF-16 keep their encoding, binary files
+    /// are skipped.
+    #[test]
+    fn scan_encodings() {
+        let dir = Path::new("tests/testfiles/encoding");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        // "Größe" in Latin-1:
+        let latin1 = b"// +IN Slide\nint Gr\xf6\xdfe = 1;\n// -IN Slide\n";
+        fs::write(dir.join("src/Latin1.java"), latin1).unwrap();
+        let utf16 = |text: &str| {
+            let mut bytes = vec![0xff, 0xfe]; // byte order mark
+            for unit in text.encode_utf16() {
+                bytes.extend(unit.to_le_bytes());
+            }
+            bytes
+        };
+        let source = utf16("# +OUT\nhidden\n# -OUT\nx = 1\n");
+        fs::write(dir.join("src/Utf16.py"), source).unwrap();
+        fs::write(dir.join("src/Binary.java"), b"\xca\xfe\xba\xbe\x00\x00").unwrap();
+        let s = &public_config()
+            .src_dir(dir.join("src"))
+            .snippet_dest_dir(dir.join("snippets"))
+            .src_dest_dir(dir.join("src_dest"));
+        assert_eq!(scan(s), Ok(()));
+        let read = |file: &str| fs::read(dir.join(file)).unwrap();
+        assert_eq!(
+            read("snippets/Latin1_Slide.java"),
+            b"int Gr\xf6\xdfe = 1;\n"
+        );
+        assert_eq!(read("src_dest/Utf16.py"), utf16("x = 1\n"));
+        assert!(!dir.join("src_dest/Binary.java").exists());
+    }
+
+    /// Markdown files use the comment symbols passed with `-c`.
+    #[test]
+    fn scan_markdown() {
+        let dir = Path::new("tests/testfiles/markdown");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let text = "# Exercise\n// +EXC\nSECRET SOLUTION\n// -EXC\nEnd\n";
+        fs::write(dir.join("src/Notes.md"), text).unwrap();
+        let s = &public_config()
+            .src_dir(dir.join("src"))
+            .snippet_dest_dir(dir.join("snippets"))
+            .src_dest_dir(dir.join("src_dest"))
+            .file_suffix([".md"]);
+        assert_eq!(scan(s), Ok(()));
+        let notes = fs::read_to_string(dir.join("src_dest/Notes.md")).unwrap();
+        assert_eq!(notes, "# Exercise\nEnd\n");
+    }
+
+    /// Merged snippets are updated from the unmodified files, too.
+    #[test]
+    fn scan_merged_unmodified() {
+        let dir = Path::new("tests/testfiles/merge");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let fragment = |text: &str| format!("// +IN @M\n{}\n// -IN @M\n", text);
+        fs::write(dir.join("src/A.java"), fragment("a = 1;")).unwrap();
+        fs::write(dir.join("src/B.java"), fragment("b = 1;")).unwrap();
+        let s = &public_config()
+            .src_dir(dir.join("src"))
+            .snippet_dest_dir(dir.join("snippets"))
+            .src_dest_dir(dir.join("src_dest"))
+            .metadata(false)
+            .force_update(false);
+        let read = || fs::read_to_string(dir.join("snippets/@M.java")).unwrap();
+        assert_eq!(scan(s), Ok(()));
+        assert_eq!(read(), "// A.java\na = 1;\n// B.java\nb = 1;\n");
+        // Only A is modified, B is parsed again for @M:
+        fs::write(dir.join("src/A.java"), fragment("a = 2;")).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(60);
+        let a = File::options().write(true).open(dir.join("src/A.java"));
+        a.unwrap().set_modified(later).unwrap();
+        assert_eq!(scan(s), Ok(()));
+        assert_eq!(read(), "// A.java\na = 2;\n// B.java\nb = 1;\n");
+    }
+
+    /// A file which cannot be written is an error, not a panic.
+    #[test]
+    fn scan_write_error() {
+        let dir = Path::new("tests/testfiles/unwritable");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/Foo.java"), "int a = 1;\n").unwrap();
+        // A directory is in the way of the snippet:
+        fs::create_dir_all(dir.join("snippets/Foo.java")).unwrap();
+        let s = &public_config()
+            .src_dir(dir.join("src"))
+            .snippet_dest_dir(dir.join("snippets"))
+            .src_dest_dir(dir.join("src_dest"));
+        let e = scan(s).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Io);
+        assert_eq!(e.file, Some(dir.join("snippets/Foo.java")));
+    }
+
+    /// A configured UTF-16 encoding reads files without byte order mark.
+    #[test]
+    fn scan_utf16_without_bom() {
+        let dir = Path::new("tests/testfiles/encoding/utf16");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let utf16 =
+            |text: &str| -> Vec<u8> { text.encode_utf16().flat_map(|u| u.to_le_bytes()).collect() };
+        fs::write(dir.join("src/A.java"), utf16("// +OUT\nb;\n// -OUT\na;\n")).unwrap();
+        let options = ParseOptions::new()
+            .comment(["//"])
+            .encoding(read_encoding("utf-16le").unwrap());
+        let s = &public_config()
+            .src_dir(dir.join("src"))
+            .snippet_dest_dir(dir.join("snippets"))
+            .src_dest_dir(dir.join("src_dest"))
+            .options(options);
+        assert_eq!(scan(s), Ok(()));
+        assert_eq!(
+            fs::read(dir.join("src_dest/A.java")).unwrap(),
+            utf16("a;\n")
+        );
+    }
+
+    /// Check all files without writing any files.
+    #[test]
+    fn check_valid_files() {
+        let s = &public_config()
+            .snippet_dest_dir("tests/testfiles/check/snippets")
+            .src_dest_dir("tests/testfiles/check/src_dest");
+        let report = check(s).unwrap();
+        assert_eq!(report.files, 10);
+        assert!(report.errors.is_empty());
+        assert!(!Path::new("tests/testfiles/check").exists());
+    }
+
+    /// The errors of all files are collected.
+    #[test]
+    fn check_broken_files() {
+        let s = &public_config().src_dir("tests/testfiles/broken");
+        let report = check(s).unwrap();
+        assert_eq!(report.files, 4);
+        let warnings: Vec<String> = report.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "tests/testfiles/broken/Testfile_Misspelled.java:3: \
+                 Unknown directive +EXCSUSBT, did you mean +EXCSUBST?"
+            ]
+        );
+        let errors: Vec<(ErrorKind, String)> = report
+            .errors
+            .iter()
+            .map(|e| (e.kind, e.to_string()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    ErrorKind::Crossing,
+                    "tests/testfiles/broken/Testfile_Crossing.java:6: \
+                     Crossing regions: +EXC is still open"
+                        .to_string()
+                ),
+                (
+                    ErrorKind::Unclosed,
+                    "tests/testfiles/broken/Testfile_Unclosed.java:2: +OUT is not closed"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    /// Snippets are included into copies of the documents in docs.
+    #[test]
+    fn weave_documents() {
+        let dir = Path::new("tests/testfiles/weave/docs");
+        fs::create_dir_all(dir).unwrap();
+        let mut files = Vec::new();
+        for doc in ["Weave.md", "Weave.tex"] {
+            fs::copy(Path::new("tests/testfiles/docs").join(doc), dir.join(doc)).unwrap();
+            let templ = doc.replace('.', "-public.");
+            files.push((dir.join(doc), src_templ().join(templ)));
+        }
+        let s = &public_config().document(files.iter().map(|(doc, _)| doc.clone()));
+        assert_eq!(weave(s), Ok(()));
+        assert!(check_files(&files, s));
+        // Weaving again keeps the documents:
+        assert_eq!(weave(s), Ok(()));
+        assert!(check_files(&files, s));
+    }
+
+    /// Snippets are dedented for documents like in `scan`, whole files not.
+    #[test]
+    fn weave_dedent() {
+        let dir = Path::new("tests/testfiles/weave/dedent");
+        fs::create_dir_all(dir).unwrap();
+        let doc = dir.join("Weave.md");
+        fs::copy("tests/testfiles/docs/Weave.md", &doc).unwrap();
+        let s = &public_config()
+            .dedent(true)
+            .indent(4)
+            .document([doc.clone()]);
+        assert_eq!(weave(s), Ok(()));
+        let text = fs::read_to_string(&doc).unwrap();
+        assert!(text.contains("```java\n// ...\n    int a = 1;\n// ...\n```"));
+        assert!(text.contains("```py\ndef foo():\n    a = 1\n"));
+    }
+
+    /// A document must only include existing snippets.
+    #[test]
+    fn weave_unknown_snippet() {
+        let dir = Path::new("tests/testfiles/weave/unknown");
+        fs::create_dir_all(dir).unwrap();
+        let doc = dir.join("Unknown.md");
+        fs::write(
+            &doc,
+            "Text\n<!-- snips:include Testfile_IN_Slide.java#Foo -->\n",
+        )
+        .unwrap();
+        let s = &public_config().document([doc.clone()]);
+        let e = weave(s).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Include);
+        assert_eq!(
+            e.to_string(),
+            "tests/testfiles/weave/unknown/Unknown.md:2: \
+             Snippet Foo not found in Testfile_IN_Slide.java"
+        );
     }
 }
@@ -0,0 +1,8 @@
+public class Testfile_Crossing {
+    // +IN Slide
+    int a;
+    // +EXC
+    int b;
+    // -IN Slide
+    // -EXC
+}
@@ -0,0 +1,6 @@
+public class Testfile_Misspelled {
+    int a() {
+        // +EXCSUSBT 4 return 0;
+        return 42;
+    }
+}
@@ -0,0 +1,4 @@
+public class Testfile_Unclosed {
+    // +OUT
+    int a;
+}
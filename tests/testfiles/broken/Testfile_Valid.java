@@ -0,0 +1,5 @@
+public class Testfile_Valid {
+    // +IN Slide
+    int a;
+    // -IN Slide
+}
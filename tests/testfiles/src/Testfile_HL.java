@@ -0,0 +1,11 @@
+public class Foo {
+  public static void main(String[] args) {
+    // +IN Slide
+    int a = 1;
+    // +HL
+    a = a + 1;
+    // -HL
+    System.out.println("Value is " + a);
+    // -IN Slide
+  }
+}
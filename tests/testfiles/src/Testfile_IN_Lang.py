@@ -0,0 +1,6 @@
+def foo():
+    # +IN Slide
+    a = 1
+    # -IN Slide
+    // +OUT is no marker in Python
+    return a
@@ -0,0 +1,5 @@
+public interface Observer {
+    // +IN @Observer
+    void update(String event);
+    // -IN @Observer
+}
@@ -0,0 +1,15 @@
+import java.util.ArrayList;
+
+public class Subject {
+    // +IN @Observer
+    private List<Observer> observers = new ArrayList<>();
+
+    // +HL
+    void notify(String event) {
+    // -HL
+        // +EXC
+        observers.forEach(o -> o.update(event));
+        // -EXC
+    }
+    // -IN @Observer
+}
@@ -0,0 +1,12 @@
+// Testfile_MERGE_Observer.java
+// ...
+    void update(String event);
+// ...
+// dir1/Testfile_MERGE_Subject.java
+// ...
+    private List<Observer> observers = new ArrayList<>();
+
+    void notify(String event) {
+        observers.forEach(o -> o.update(event));
+    }
+// ...
@@ -3,4 +3,4 @@ public class Foo {
     int a = 1;
     System.out.println("Value is " + a);
   }
-}
+}
\ No newline at end of file
@@ -1,3 +1,3 @@
-...
+// ...
     int a = 1;
-...
\ No newline at end of file
+// ...